@@ -0,0 +1,372 @@
+//! Builds, inspects and queries index files from the command line
+//!
+//! Indexes are written in the mapped format (see [MappedHPRTree]) with the number of the input record (starting at 0, not counting the CSV header or blank NDJSON lines) as the element, so results can be looked up in the input
+//!
+//! - `hprtree build <input> <index> [--format csv|ndjson] [--x <column>] [--y <column>] [--node-capacity <n>]`
+//! - `hprtree query <index> <minx> <miny> <maxx> <maxy>`
+//! - `hprtree nearest <index> <x> <y> <k>`
+//! - `hprtree within <index> <x> <y> <radius>`
+//! - `hprtree stats <index>`
+//!
+//! Query results are printed as CSV with the columns row, x and y, nearest and within add the distance to the given point
+
+use std::{
+    env,
+    error::Error,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    ops::ControlFlow,
+    path::Path,
+    process,
+    str::FromStr,
+};
+
+use hprtree::{
+    BBox, HPRTreeWrappingBuilder, MappedHPRTree, MappedItem, MappedTreeError, Point,
+    DEFAULT_NODE_CAPACITY,
+};
+
+const USAGE: &str = "usage:
+  hprtree build <input> <index> [--format csv|ndjson] [--x <column>] [--y <column>] [--node-capacity <n>]
+  hprtree query <index> <minx> <miny> <maxx> <maxy>
+  hprtree nearest <index> <x> <y> <k>
+  hprtree within <index> <x> <y> <radius>
+  hprtree stats <index>";
+
+/// Why a command failed, usage errors are reported together with the usage
+enum CliError {
+    Usage(String),
+    Failed(Box<dyn Error>),
+}
+
+impl<E> From<E> for CliError
+where
+    E: Error + 'static,
+{
+    fn from(err: E) -> Self {
+        CliError::Failed(Box::new(err))
+    }
+}
+
+fn usage(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+fn failed(message: impl Into<String>) -> CliError {
+    CliError::Failed(message.into().into())
+}
+
+/// Prefixes an error with the path of the file it occurred with
+fn in_file<E: Display>(path: &str) -> impl FnOnce(E) -> CliError + '_ {
+    move |err| failed(format!("{path}: {err}"))
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| usage(format!("invalid {name}: {value}")))
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Ndjson,
+}
+
+fn parse_coordinate(row: usize, column: &str, value: Option<f64>) -> Result<f64, CliError> {
+    // NaN and infinite coordinates would end up in the bounds of every node above them
+    value
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| failed(format!("row {row}: missing or invalid {column}")))
+}
+
+fn read_csv(path: &str, x_column: &str, y_column: &str) -> Result<Vec<Point<f64>>, CliError> {
+    let mut reader = csv::Reader::from_path(path).map_err(in_file(path))?;
+    let headers = reader.headers().map_err(in_file(path))?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| failed(format!("{path} has no column {name}")))
+    };
+    let (x_index, y_index) = (column(x_column)?, column(y_column)?);
+
+    let mut points = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(in_file(path))?;
+        let field = |index: usize| {
+            record
+                .get(index)
+                .and_then(|field| field.trim().parse().ok())
+        };
+        points.push(Point {
+            x: parse_coordinate(row, x_column, field(x_index))?,
+            y: parse_coordinate(row, y_column, field(y_index))?,
+        });
+    }
+    Ok(points)
+}
+
+fn read_ndjson(path: &str, x_column: &str, y_column: &str) -> Result<Vec<Point<f64>>, CliError> {
+    let reader = BufReader::new(File::open(path).map_err(in_file(path))?);
+    let mut points = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(in_file(path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = points.len();
+        let value: serde_json::Value =
+            serde_json::from_str(&line).map_err(|err| failed(format!("row {row}: {err}")))?;
+        let field = |name: &str| value.get(name).and_then(serde_json::Value::as_f64);
+        points.push(Point {
+            x: parse_coordinate(row, x_column, field(x_column))?,
+            y: parse_coordinate(row, y_column, field(y_column))?,
+        });
+    }
+    Ok(points)
+}
+
+fn build(args: &[String]) -> Result<(), CliError> {
+    let mut positional = Vec::new();
+    let mut format = None;
+    let mut x_column = "x".to_string();
+    let mut y_column = "y".to_string();
+    let mut node_capacity = DEFAULT_NODE_CAPACITY;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| usage(format!("missing value for {arg}")))?;
+        match arg.as_str() {
+            "--format" => {
+                format = Some(match value.as_str() {
+                    "csv" => Format::Csv,
+                    "ndjson" => Format::Ndjson,
+                    _ => return Err(usage(format!("unknown format {value}"))),
+                })
+            }
+            "--x" => x_column = value.clone(),
+            "--y" => y_column = value.clone(),
+            "--node-capacity" => node_capacity = parse("node capacity", value)?,
+            _ => return Err(usage(format!("unknown option {arg}"))),
+        }
+    }
+    let [input, output] = positional[..] else {
+        return Err(usage("build takes an input and an index file"));
+    };
+    if !(2..=u32::MAX as usize).contains(&node_capacity) {
+        return Err(usage("the node capacity has to be at least 2"));
+    }
+    let format = match format {
+        Some(format) => format,
+        None => match Path::new(input).extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("ndjson" | "jsonl") => Format::Ndjson,
+            _ => {
+                return Err(usage(format!(
+                    "cannot tell the format of {input}, pass --format"
+                )))
+            }
+        },
+    };
+
+    let points = match format {
+        Format::Csv => read_csv(input, &x_column, &y_column)?,
+        Format::Ndjson => read_ndjson(input, &x_column, &y_column)?,
+    };
+    let mut builder = HPRTreeWrappingBuilder::new(points.len()).with_node_capacity(node_capacity);
+    for (row, point) in points.into_iter().enumerate() {
+        builder.insert(row as u64, point);
+    }
+    let tree = builder.build();
+
+    let mut writer = BufWriter::new(File::create(output).map_err(in_file(output))?);
+    tree.write_mapped(&mut writer).map_err(in_file(output))?;
+    writer.flush().map_err(in_file(output))?;
+    eprintln!("indexed {} rows into {output}", tree.len());
+    Ok(())
+}
+
+/// The elements of indexes written by build are u64 row numbers
+type Index<'a> = MappedHPRTree<'a, u64, f64>;
+
+fn open_index<'a>(path: &str, bytes: &'a [u8]) -> Result<Index<'a>, CliError> {
+    MappedHPRTree::from_bytes(bytes).map_err(|err| match err {
+        MappedTreeError::ItemSize { .. } => {
+            failed(format!("{path}: not an index written by hprtree build"))
+        }
+        err => failed(format!("{path}: {err}")),
+    })
+}
+
+fn print_rows<'a>(
+    items: impl IntoIterator<Item = MappedItem<'a, u64, f64>>,
+    center: &Point<f64>,
+) -> Result<(), CliError> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    writeln!(out, "row,x,y,distance")?;
+    for item in items {
+        let bbox = item.bbox();
+        let distance = (bbox.minx - center.x).hypot(bbox.miny - center.y);
+        writeln!(out, "{},{},{},{distance}", item.get(), bbox.minx, bbox.miny)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn query(args: &[String]) -> Result<(), CliError> {
+    let [index, minx, miny, maxx, maxy] = args else {
+        return Err(usage("query takes an index file and minx miny maxx maxy"));
+    };
+    let query_env = BBox::new(
+        parse("minx", minx)?,
+        parse("miny", miny)?,
+        parse("maxx", maxx)?,
+        parse("maxy", maxy)?,
+    );
+    let bytes = fs::read(index).map_err(in_file(index))?;
+    let mapped = open_index(index, &bytes)?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    writeln!(out, "row,x,y")?;
+    let mut result = Ok(());
+    let _ = mapped.query_visit(&query_env, |item| {
+        let bbox = item.bbox();
+        result = writeln!(out, "{},{},{}", item.get(), bbox.minx, bbox.miny);
+        if result.is_err() {
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    });
+    result?;
+    out.flush()?;
+    Ok(())
+}
+
+fn nearest(args: &[String]) -> Result<(), CliError> {
+    let [index, x, y, k] = args else {
+        return Err(usage("nearest takes an index file and x y k"));
+    };
+    let center = Point {
+        x: parse("x", x)?,
+        y: parse("y", y)?,
+    };
+    let k = parse("k", k)?;
+    let bytes = fs::read(index).map_err(in_file(index))?;
+    let mapped = open_index(index, &bytes)?;
+
+    print_rows(mapped.nearest_k(&center, k), &center)
+}
+
+fn within(args: &[String]) -> Result<(), CliError> {
+    let [index, x, y, radius] = args else {
+        return Err(usage("within takes an index file and x y radius"));
+    };
+    let center = Point {
+        x: parse("x", x)?,
+        y: parse("y", y)?,
+    };
+    let radius = parse("radius", radius)?;
+    let bytes = fs::read(index).map_err(in_file(index))?;
+    let mapped = open_index(index, &bytes)?;
+
+    print_rows(mapped.query_within_distance(&center, radius), &center)
+}
+
+fn stats(args: &[String]) -> Result<(), CliError> {
+    let [index] = args else {
+        return Err(usage("stats takes an index file"));
+    };
+    let bytes = fs::read(index).map_err(in_file(index))?;
+    let tree = open_index(index, &bytes)?;
+
+    let node_capacity = tree.node_capacity();
+    let extent_area = tree.extent().area();
+    let mut node_count = 0;
+    let mut child_count = 0;
+    let mut out = io::stdout().lock();
+    writeln!(out, "rows: {}", tree.len())?;
+    writeln!(out, "node capacity: {node_capacity}")?;
+    writeln!(out, "depth: {}", tree.depth())?;
+    let mut children = tree.len();
+    for layer in 0..tree.depth() {
+        let bounds = tree.layer_bounds(layer);
+        let nodes = bounds.len();
+        // the summed area of the nodes of a layer grows with their overlap and the empty space they cover
+        let node_area: f64 = bounds.map(|bbox| bbox.area()).sum();
+        node_count += nodes;
+        child_count += children;
+        let fill = children as f64 / (nodes * node_capacity) as f64 * 100f64;
+        write!(out, "layer {layer}: {nodes} nodes, {fill:.1}% full")?;
+        if extent_area > 0f64 {
+            write!(out, ", {:.2}x the extent area", node_area / extent_area)?;
+        }
+        writeln!(out)?;
+        // the nodes of a layer are the children of the one above it
+        children = nodes;
+    }
+    writeln!(out, "nodes: {node_count}")?;
+    if node_count > 0 {
+        let fill = child_count as f64 / (node_count * node_capacity) as f64 * 100f64;
+        writeln!(out, "fill: {fill:.1}%")?;
+    }
+    if tree.is_empty() {
+        writeln!(out, "extent: empty")?;
+    } else {
+        let extent = tree.extent();
+        writeln!(
+            out,
+            "extent: {} {} {} {}",
+            extent.minx, extent.miny, extent.maxx, extent.maxy
+        )?;
+    }
+    // what the tree is made of, the rest of the file is the header and the extent
+    let nodes_size = tree.nodes_size_in_bytes();
+    let rows_size = tree.items_size_in_bytes();
+    writeln!(
+        out,
+        "structure size: {} bytes ({nodes_size} for nodes, {rows_size} for rows)",
+        nodes_size + rows_size
+    )?;
+    writeln!(out, "file size: {} bytes", bytes.len())?;
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(usage("missing command"));
+    };
+    match command.as_str() {
+        "build" => build(args),
+        "query" => query(args),
+        "nearest" => nearest(args),
+        "within" => within(args),
+        "stats" => stats(args),
+        _ => Err(usage(format!("unknown command {command}"))),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    match run(&args) {
+        Ok(()) => {}
+        Err(CliError::Usage(message)) => {
+            eprintln!("{message}\n{USAGE}");
+            process::exit(2);
+        }
+        Err(CliError::Failed(err)) => {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
+    }
+}
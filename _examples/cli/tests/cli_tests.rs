@@ -0,0 +1,145 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+fn hprtree(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hprtree"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout_lines(output: &Output) -> Vec<String> {
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout.clone())
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hprtree-cli-{}-{name}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn build_and_query_test() {
+    let dir = temp_dir("build_and_query");
+    let input = dir.join("points.csv");
+    let index = dir.join("points.idx");
+
+    // a 50 x 40 grid, row i is at (i % 50, i / 50)
+    let mut csv = String::from("id,name,lon,lat\n");
+    for i in 0..2_000 {
+        csv.push_str(&format!("{i},\"name, {i}\",{},{}\n", i % 50, i / 50));
+    }
+    fs::write(&input, csv).unwrap();
+    let output = hprtree(&[
+        "build",
+        path_str(&input),
+        path_str(&index),
+        "--x",
+        "lon",
+        "--y",
+        "lat",
+        "--node-capacity",
+        "8",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+
+    let lines = stdout_lines(&hprtree(&[
+        "query",
+        path_str(&index),
+        "0.5",
+        "0.5",
+        "2.5",
+        "1.5",
+    ]));
+    assert!(lines[0] == "row,x,y");
+    let mut rows = lines[1..].to_vec();
+    rows.sort();
+    assert!(rows == ["51,1,1", "52,2,1"]);
+
+    let lines = stdout_lines(&hprtree(&["nearest", path_str(&index), "10.1", "3", "2"]));
+    assert!(lines[0] == "row,x,y,distance");
+    assert!(lines.len() == 3);
+    assert!(lines[1].starts_with("160,10,3,"));
+    assert!(lines[2].starts_with("161,11,3,"));
+
+    let lines = stdout_lines(&hprtree(&["within", path_str(&index), "10", "3", "1"]));
+    assert!(lines.len() == 6);
+    assert!(lines.contains(&"160,10,3,0".to_string()));
+
+    let lines = stdout_lines(&hprtree(&["stats", path_str(&index)]));
+    assert!(lines.contains(&"rows: 2000".to_string()));
+    assert!(lines.contains(&"node capacity: 8".to_string()));
+    assert!(lines.contains(&"depth: 4".to_string()));
+    assert!(lines.contains(&"extent: 0 0 49 39".to_string()));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("layer 0: 250 nodes, 100.0% full, ")));
+    // 287 nodes of 32 bytes and 5 layer offsets, 2000 rows of a bbox and a u64
+    assert!(
+        lines.contains(&"structure size: 89224 bytes (9224 for nodes, 80000 for rows)".to_string())
+    );
+    let file_size = fs::metadata(&index).unwrap().len();
+    assert!(lines.contains(&format!("file size: {file_size} bytes")));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ndjson_and_errors_test() {
+    let dir = temp_dir("ndjson_and_errors");
+    let input = dir.join("points.ndjson");
+    let index = dir.join("points.idx");
+
+    // blank lines are not counted as rows
+    fs::write(
+        &input,
+        "{\"x\": 1, \"y\": 2}\n\n{\"x\": 3.5, \"y\": -1, \"name\": \"a\"}\n",
+    )
+    .unwrap();
+    assert!(hprtree(&["build", path_str(&input), path_str(&index)])
+        .status
+        .success());
+    let lines = stdout_lines(&hprtree(&["query", path_str(&index), "3", "-2", "4", "0"]));
+    assert!(lines == ["row,x,y", "1,3.5,-1"]);
+    // the index has fewer rows than the node capacity, so there are no nodes to search
+    let lines = stdout_lines(&hprtree(&["nearest", path_str(&index), "0", "0", "5"]));
+    assert!(
+        lines
+            == [
+                "row,x,y,distance",
+                "0,1,2,2.23606797749979",
+                "1,3.5,-1,3.640054944640259"
+            ]
+    );
+    let lines = stdout_lines(&hprtree(&["stats", path_str(&index)]));
+    assert!(lines.contains(&"depth: 0".to_string()));
+
+    fs::write(&input, "{\"x\": 1, \"y\": 2}\n{\"x\": 1}\n").unwrap();
+    let output = hprtree(&["build", path_str(&input), path_str(&index)]);
+    assert!(output.status.code() == Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("row 1"));
+
+    // usage errors exit with 2, failures with 1
+    assert!(hprtree(&["frobnicate"]).status.code() == Some(2));
+    assert!(
+        hprtree(&["query", path_str(&index), "0", "0", "1"])
+            .status
+            .code()
+            == Some(2)
+    );
+    assert!(hprtree(&["stats", path_str(&input)]).status.code() == Some(1));
+
+    fs::remove_dir_all(dir).unwrap();
+}
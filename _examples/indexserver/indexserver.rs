@@ -1,20 +1,91 @@
-extern crate hprtree;
+//! A spatial index of points served over HTTP, meant to run as a sidecar
+//!
+//! The index is a [DynamicHPRTree], writes are visible to the next read right away and only wait for the small unsorted buffer of the index to be packed. Its runs are merged in the background every `--merge-interval-ms`, the merged run is built without holding the lock so reads and writes go on in the meantime. The index is loaded from `--snapshot` on start and saved there on shutdown (SIGINT/SIGTERM)
+//!
+//! - `POST /add` a single element `{"x": .., "y": .., "data": ..}`, responds with its id
+//! - `POST /bulk` one element per line (NDJSON), responds with their ids
+//! - `POST /geojson` a FeatureCollection of Points, the properties of each feature become its data
+//! - `POST /find` the elements within `{"minx": .., "miny": .., "maxx": .., "maxy": ..}`
+//! - `POST /nearest` the `k` elements closest to `{"x": .., "y": .., "k": ..}`
+//! - `POST /within` the elements within `radius` of `{"x": .., "y": .., "radius": ..}`
+//! - `DELETE /items/{id}` removes an element
+//! - `GET /health` and `GET /stats`
 
-use std::sync::Mutex;
+use std::{
+    env,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    mem,
+    path::PathBuf,
+    process,
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread,
+    time::Duration,
+};
 
 use actix_web::{
-    post,
-    web::{self, Data},
+    delete, get, post,
+    web::{self, Bytes, Data},
     App, HttpResponse, HttpServer, Responder,
 };
-use hprtree::{BBox, HPRTree, HPRTreeBuilder, SpatiallyIndexable};
+use hprtree::{BBox, DynamicHPRTree, HPRTree, ItemId, Point, SpatiallyIndexable};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const USAGE: &str = "usage: indexserver [--bind <host:port>] [--snapshot <path> | --no-snapshot] [--merge-interval-ms <ms>] [--max-payload-mb <mb>]";
+
+struct Config {
+    bind: String,
+    snapshot: Option<PathBuf>,
+    merge_interval: Duration,
+    max_payload: usize,
+}
+
+impl Config {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config {
+            bind: "localhost:3030".to_string(),
+            snapshot: Some(PathBuf::from("indexserver-snapshot.json")),
+            merge_interval: Duration::from_millis(100),
+            max_payload: 64 << 20,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-snapshot" => {
+                    config.snapshot = None;
+                    continue;
+                }
+                "--bind" | "--snapshot" | "--merge-interval-ms" | "--max-payload-mb" => {}
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {arg}"))?;
+            let invalid = |_| format!("invalid value for {arg}: {value}");
+            match arg.as_str() {
+                "--bind" => config.bind = value.clone(),
+                "--snapshot" => config.snapshot = Some(PathBuf::from(&value)),
+                "--merge-interval-ms" => {
+                    let millis = value.parse().map_err(invalid)?;
+                    config.merge_interval = Duration::from_millis(millis);
+                }
+                "--max-payload-mb" => {
+                    config.max_payload = value.parse::<usize>().map_err(invalid)? << 20
+                }
+                _ => unreachable!(),
+            }
+        }
+        Ok(config)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Element {
+    pub id: usize,
     pub x: f32,
     pub y: f32,
-    pub data: String,
+    pub data: serde_json::Value,
 }
 
 impl SpatiallyIndexable for Element {
@@ -27,6 +98,33 @@ impl SpatiallyIndexable for Element {
     }
 }
 
+/// An element as sent by clients, the id is handed out by the index
+#[derive(Debug, Clone, Deserialize)]
+struct NewElement {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FeatureCollection {
+    pub features: Vec<Feature>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Feature {
+    pub geometry: Geometry,
+    #[serde(default)]
+    pub properties: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Point { coordinates: Vec<f64> },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FindBBox {
     pub minx: f32,
@@ -35,57 +133,269 @@ pub struct FindBBox {
     pub maxy: f32,
 }
 
-impl Into<BBox> for FindBBox {
-    fn into(self) -> BBox {
+impl From<FindBBox> for BBox {
+    fn from(bbox: FindBBox) -> Self {
         BBox {
-            minx: self.minx,
-            miny: self.miny,
-            maxx: self.maxx,
-            maxy: self.maxy,
+            minx: bbox.minx,
+            miny: bbox.miny,
+            maxx: bbox.maxx,
+            maxy: bbox.maxy,
         }
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct Nearest {
+    pub x: f32,
+    pub y: f32,
+    pub k: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Within {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f64,
+}
+
+/// Inserts an element under the id the index hands out for it, which is kept in the element for the responses
+fn insert(tree: &mut DynamicHPRTree<Element>, element: NewElement) -> usize {
+    let id = tree.next_id().0;
+    tree.insert(Element {
+        id,
+        x: element.x,
+        y: element.y,
+        data: element.data,
+    });
+    id
+}
+
+type Index = RwLock<DynamicHPRTree<Element>>;
+
+// the state stays consistent even if a handler panicked while holding the lock
+fn read(index: &Index) -> RwLockReadGuard<'_, DynamicHPRTree<Element>> {
+    index.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write(index: &Index) -> RwLockWriteGuard<'_, DynamicHPRTree<Element>> {
+    index.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn bad_request(message: impl Display) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({ "error": message.to_string() }))
+}
+
+fn insert_all(index: &Index, elements: Vec<NewElement>) -> HttpResponse {
+    let mut tree = write(index);
+    let ids: Vec<usize> = elements
+        .into_iter()
+        .map(|element| insert(&mut tree, element))
+        .collect();
+    HttpResponse::Ok().json(json!({ "ids": ids }))
+}
+
 #[post("/add")]
-async fn add(
-    index: Data<Mutex<HPRTree<Element>>>,
-    index_builder: Data<Mutex<HPRTreeBuilder<Element>>>,
-    data: web::Json<Element>,
-) -> impl Responder {
-    let mut index = index.lock().unwrap();
-    let mut index_builder = index_builder.lock().unwrap();
-    let data = data.into_inner();
-    index_builder.insert(data);
-    index_builder.sort_items();
-    *index = index_builder.clone().build_sorted();
+async fn add(index: Data<Index>, data: web::Json<NewElement>) -> impl Responder {
+    let id = insert(&mut write(&index), data.into_inner());
+
+    HttpResponse::Ok().json(json!({ "id": id }))
+}
+
+#[post("/bulk")]
+async fn bulk(index: Data<Index>, body: Bytes) -> impl Responder {
+    let Ok(body) = std::str::from_utf8(&body) else {
+        return bad_request("the body is not valid UTF-8");
+    };
+    // every line is parsed before anything is inserted, so a bad line leaves the index untouched
+    let mut elements = Vec::new();
+    for (line_index, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<NewElement>(line) {
+            Ok(element) => elements.push(element),
+            Err(err) => return bad_request(format!("line {}: {err}", line_index + 1)),
+        }
+    }
 
-    HttpResponse::Ok()
+    insert_all(&index, elements)
+}
+
+#[post("/geojson")]
+async fn geojson(index: Data<Index>, body: Bytes) -> impl Responder {
+    let collection: FeatureCollection = match serde_json::from_slice(&body) {
+        Ok(collection) => collection,
+        Err(err) => return bad_request(err),
+    };
+    let mut elements = Vec::with_capacity(collection.features.len());
+    for (feature_index, feature) in collection.features.into_iter().enumerate() {
+        let Geometry::Point { coordinates } = feature.geometry;
+        let [x, y, ..] = coordinates[..] else {
+            return bad_request(format!(
+                "feature {feature_index}: a point needs at least two coordinates"
+            ));
+        };
+        elements.push(NewElement {
+            x: x as f32,
+            y: y as f32,
+            data: feature.properties,
+        });
+    }
+
+    insert_all(&index, elements)
 }
 
 #[post("/find")]
-async fn find(index: Data<Mutex<HPRTree<Element>>>, data: web::Json<FindBBox>) -> impl Responder {
-    let index = index.lock().unwrap();
+async fn find(index: Data<Index>, data: web::Json<FindBBox>) -> impl Responder {
     let data: BBox = data.into_inner().into();
 
-    let res = index.query(&data);
+    let res = read(&index).query(&data);
+
+    HttpResponse::Ok().json(res)
+}
+
+#[post("/nearest")]
+async fn nearest(index: Data<Index>, data: web::Json<Nearest>) -> impl Responder {
+    let point = Point {
+        x: data.x,
+        y: data.y,
+    };
+
+    let res = read(&index).nearest_k(&point, data.k);
+
+    HttpResponse::Ok().json(res)
+}
+
+#[post("/within")]
+async fn within(index: Data<Index>, data: web::Json<Within>) -> impl Responder {
+    let center = Point {
+        x: data.x,
+        y: data.y,
+    };
+
+    let res = read(&index).query_within_distance(&center, data.radius);
 
     HttpResponse::Ok().json(res)
 }
 
+#[delete("/items/{id}")]
+async fn remove(index: Data<Index>, id: web::Path<usize>) -> impl Responder {
+    if write(&index).remove(ItemId(*id)) {
+        HttpResponse::Ok().json(json!({ "id": *id }))
+    } else {
+        HttpResponse::NotFound().json(json!({ "error": format!("unknown id {id}") }))
+    }
+}
+
+#[get("/health")]
+async fn health() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+#[get("/stats")]
+async fn stats(index: Data<Index>) -> impl Responder {
+    let tree = read(&index);
+    let extent = tree.extent();
+    let extent = if tree.is_empty() {
+        serde_json::Value::Null
+    } else {
+        json!([extent.minx, extent.miny, extent.maxx, extent.maxy])
+    };
+
+    HttpResponse::Ok().json(json!({
+        "len": tree.len(),
+        "runs": tree.run_count(),
+        "next_id": tree.next_id().0,
+        "extent": extent,
+    }))
+}
+
+/// Loads the index saved on shutdown, the tree keeps the ids of the elements and does not hand out those of removed ones again
+fn load_snapshot(config: &Config) -> io::Result<DynamicHPRTree<Element>> {
+    let Some(path) = &config.snapshot else {
+        return Ok(DynamicHPRTree::new());
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(DynamicHPRTree::new()),
+        Err(err) => return Err(err),
+    };
+    let tree: HPRTree<Element> = serde_json::from_reader(BufReader::new(file))?;
+    println!("loaded {} elements from {}", tree.len(), path.display());
+    Ok(DynamicHPRTree::from(tree))
+}
+
+fn save_snapshot(config: &Config, index: &Index) -> io::Result<()> {
+    let Some(path) = &config.snapshot else {
+        return Ok(());
+    };
+    // the server has stopped, so the index is taken out and compacted into a single tree
+    let tree = mem::take(&mut *write(index)).into_tree();
+    // written next to the previous snapshot and renamed over it, so a crash while saving does not lose it
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    serde_json::to_writer(&mut writer, &tree)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    println!("saved {} elements to {}", tree.len(), path.display());
+    Ok(())
+}
+
+/// Merges the runs of the index that are due, the merged run is built while neither reads nor writes are blocked and only swapped in under the lock
+fn merge_runs(index: &Index) {
+    loop {
+        let Some(pending) = read(index).start_merge() else {
+            break;
+        };
+        let merged = pending.build();
+        if !write(index).finish_merge(merged) {
+            // the merged runs were rebuilt in the meantime, the merge is retried next time
+            return;
+        }
+    }
+    // with no runs due for merging this only compacts runs with many removed elements, which is rare enough to be done under the lock
+    write(index).maintain();
+}
+
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let builder = HPRTreeBuilder::<Element>::new(16);
-    let index_builder = Data::new(Mutex::new(builder.clone()));
-    let index = Data::new(Mutex::new(builder.build()));
+async fn main() -> io::Result<()> {
+    if env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let config = Config::from_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        process::exit(2);
+    });
+    let index = Data::new(RwLock::new(load_snapshot(&config)?));
 
+    let merger = index.clone();
+    let merge_interval = config.merge_interval;
+    thread::spawn(move || loop {
+        thread::sleep(merge_interval);
+        merge_runs(&merger);
+    });
+
+    let server_index = index.clone();
+    let max_payload = config.max_payload;
     HttpServer::new(move || {
         App::new()
-            .app_data(index_builder.clone())
-            .app_data(index.clone())
+            .app_data(server_index.clone())
+            .app_data(web::PayloadConfig::new(max_payload))
+            .app_data(web::JsonConfig::default().limit(max_payload))
             .service(add)
+            .service(bulk)
+            .service(geojson)
             .service(find)
+            .service(nearest)
+            .service(within)
+            .service(remove)
+            .service(health)
+            .service(stats)
     })
-    .bind(("localhost", 3030))?
+    .bind(&config.bind)?
     .run()
-    .await
+    .await?;
+
+    save_snapshot(&config, &index)
 }
@@ -0,0 +1,215 @@
+use std::{
+    env, fs,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+/// A running server, which is killed when dropped
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(snapshot: Option<&Path>) -> Self {
+        // a free port is looked up by binding to port 0, it is released again right before the server binds it
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_indexserver"));
+        command
+            .args(["--bind", &format!("127.0.0.1:{port}")])
+            .args(["--merge-interval-ms", "10"])
+            .stdout(Stdio::null());
+        match snapshot {
+            Some(path) => command.args(["--snapshot", path.to_str().unwrap()]),
+            None => command.arg("--no-snapshot"),
+        };
+        let server = Server {
+            child: command.spawn().unwrap(),
+            port,
+        };
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                assert!(server.get("/health")["status"] == "ok");
+                return server;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("the server did not start listening on port {port}");
+    }
+
+    /// Sends a request and returns the status and the JSON body of the response
+    fn request(&self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn get(&self, path: &str) -> Value {
+        let (status, response) = self.request("GET", path, "");
+        assert!(status == 200, "{status} {response}");
+        response
+    }
+
+    fn post(&self, path: &str, body: Value) -> Value {
+        let (status, response) = self.request("POST", path, &body.to_string());
+        assert!(status == 200, "{status} {response}");
+        response
+    }
+
+    /// Stops the server the way a service manager would, which saves the snapshot
+    #[cfg(unix)]
+    fn terminate(mut self) {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hprtree-indexserver-{}-{name}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns the ids of the elements in a response, sorted
+fn ids(elements: &Value) -> Vec<u64> {
+    let mut ids: Vec<u64> = elements
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|element| element["id"].as_u64().unwrap())
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Posts a 20 x 20 grid of points with their index as data, element i is at (i % 20, i / 20)
+fn post_grid(server: &Server) {
+    let mut body = String::new();
+    for i in 0..400 {
+        body.push_str(&json!({ "x": i % 20, "y": i / 20, "data": i }).to_string());
+        body.push('\n');
+    }
+    let (status, response) = server.request("POST", "/bulk", &body);
+    assert!(status == 200, "{status} {response}");
+    assert!(response["ids"] == json!((0..400).collect::<Vec<u64>>()));
+}
+
+#[test]
+fn bulk_and_query_test() {
+    let server = Server::start(None);
+    post_grid(&server);
+
+    let found = server.post(
+        "/find",
+        json!({ "minx": 2.5, "miny": 2.5, "maxx": 4.5, "maxy": 4.5 }),
+    );
+    assert!(ids(&found) == [63, 64, 83, 84]);
+    assert!(found[0]["data"] == found[0]["id"]);
+
+    // (5, 5) itself and the four points at a distance of 1 around it
+    let nearest = server.post("/nearest", json!({ "x": 5.1, "y": 5, "k": 5 }));
+    assert!(nearest.as_array().unwrap().len() == 5);
+    assert!(nearest[0]["id"] == 105);
+    assert!(nearest[1]["id"] == 106);
+    assert!(ids(&nearest) == [85, 104, 105, 106, 125]);
+
+    let within = server.post("/within", json!({ "x": 5, "y": 5, "radius": 1.5 }));
+    assert!(ids(&within) == [84, 85, 86, 104, 105, 106, 124, 125, 126]);
+
+    let stats = server.get("/stats");
+    assert!(stats["len"] == 400);
+    assert!(stats["extent"] == json!([0.0, 0.0, 19.0, 19.0]));
+
+    // a bad line is reported and leaves the index untouched
+    let (status, response) = server.request("POST", "/bulk", "{\"x\": 1, \"y\": 1}\nnot json\n");
+    assert!(status == 400);
+    assert!(response["error"].as_str().unwrap().starts_with("line 2"));
+    assert!(server.get("/stats")["len"] == 400);
+}
+
+#[test]
+fn delete_test() {
+    let server = Server::start(None);
+    post_grid(&server);
+    let id = server.post("/add", json!({ "x": 5, "y": 5, "data": "extra" }))["id"].clone();
+    assert!(id == 400);
+
+    let (status, response) = server.request("DELETE", "/items/105", "");
+    assert!(status == 200, "{status} {response}");
+    assert!(response["id"] == 105);
+    let (status, _) = server.request("DELETE", "/items/105", "");
+    assert!(status == 404);
+    let (status, _) = server.request("DELETE", "/items/1000", "");
+    assert!(status == 404);
+
+    // removing is visible right away, the other element at the same point is kept
+    let within = server.post("/within", json!({ "x": 5, "y": 5, "radius": 0.5 }));
+    assert!(ids(&within) == [400]);
+    let nearest = server.post("/nearest", json!({ "x": 5, "y": 5, "k": 2 }));
+    assert!(nearest[0]["id"] == 400);
+    assert!(nearest[1]["id"] != 105);
+    let stats = server.get("/stats");
+    assert!(stats["len"] == 400);
+    assert!(stats["next_id"] == 401);
+}
+
+#[cfg(unix)]
+#[test]
+fn snapshot_test() {
+    let dir = temp_dir("snapshot");
+    let snapshot = dir.join("snapshot.json");
+    let _ = fs::remove_file(&snapshot);
+
+    let server = Server::start(Some(&snapshot));
+    post_grid(&server);
+    let (status, _) = server.request("DELETE", "/items/399", "");
+    assert!(status == 200);
+    let query_env = json!({ "minx": 0, "miny": 0, "maxx": 10, "maxy": 10 });
+    let before = server.post("/find", query_env.clone());
+    server.terminate();
+    assert!(snapshot.exists());
+
+    let server = Server::start(Some(&snapshot));
+    assert!(server.get("/stats")["len"] == 399);
+    let after = server.post("/find", query_env);
+    assert!(ids(&after) == ids(&before));
+    let (status, _) = server.request("DELETE", "/items/0", "");
+    assert!(status == 200);
+    // the id of the removed element is not handed out again
+    assert!(server.post("/add", json!({ "x": 1, "y": 1 }))["id"] == 400);
+    server.terminate();
+
+    let server = Server::start(Some(&snapshot));
+    assert!(server.get("/stats")["len"] == 399);
+    assert!(ids(&server.post("/within", json!({ "x": 0, "y": 0, "radius": 0.5 }))).is_empty());
+}
@@ -2,7 +2,10 @@
 
 extern crate test;
 
-use hprtree::{BBox, CoordinateType, HPRTree, HPRTreeBuilder, Point, SpatiallyIndexable};
+use hprtree::{
+    join_within_distance, BBox, CoordinateType, HPRTree, HPRTreeBuilder, Point, SpatiallyIndexable,
+    DEFAULT_NODE_CAPACITY,
+};
 use test::Bencher;
 
 #[derive(Clone)]
@@ -23,8 +26,16 @@ impl SpatiallyIndexable for IndexableUsize {
 }
 
 fn build_bench_hprtree(mult: usize) -> HPRTree<IndexableUsize> {
+    build_bench_hprtree_with_node_capacity(mult, DEFAULT_NODE_CAPACITY)
+}
+
+fn build_bench_hprtree_with_node_capacity(mult: usize, node_capacity: usize) -> HPRTree<IndexableUsize> {
+    bench_builder(mult, node_capacity).build()
+}
+
+fn bench_builder(mult: usize, node_capacity: usize) -> HPRTreeBuilder<IndexableUsize> {
     let expected_size = mult * 180 * mult * 90;
-    let mut index = HPRTreeBuilder::<IndexableUsize>::new(expected_size);
+    let mut index = HPRTreeBuilder::<IndexableUsize>::new(expected_size).with_node_capacity(node_capacity);
     let mut x = -180f32;
     for i in 0..(180 * mult) {
         let mut y = -90f32;
@@ -37,7 +48,7 @@ fn build_bench_hprtree(mult: usize) -> HPRTree<IndexableUsize> {
         }
         x += 2f32 / mult as f32;
     }
-    index.build()
+    index
 }
 
 #[bench]
@@ -61,6 +72,14 @@ fn hprtree_build_bench_large(b: &mut Bencher) {
     });
 }
 
+#[cfg(feature = "rayon")]
+#[bench]
+fn hprtree_par_build_bench_large(b: &mut Bencher) {
+    b.iter(|| {
+        bench_builder(4, DEFAULT_NODE_CAPACITY).par_build();
+    });
+}
+
 #[bench]
 fn hprtree_query_bench_small(b: &mut Bencher) {
     let tree = build_bench_hprtree(1);
@@ -105,3 +124,46 @@ fn hprtree_query_bench_large(b: &mut Bencher) {
         }
     });
 }
+
+fn query_bench_node_capacity(b: &mut Bencher, node_capacity: usize) {
+    let tree = build_bench_hprtree_with_node_capacity(2, node_capacity);
+    b.iter(|| {
+        for i in 0..9 {
+            tree.query(&BBox {
+                minx: -10f32 * i as f32,
+                miny: -10f32 * i as f32,
+                maxx: 10f32 * i as f32,
+                maxy: 10f32 * i as f32,
+            });
+        }
+    });
+}
+
+#[bench]
+fn hprtree_query_bench_node_capacity_4(b: &mut Bencher) {
+    query_bench_node_capacity(b, 4);
+}
+
+#[bench]
+fn hprtree_query_bench_node_capacity_8(b: &mut Bencher) {
+    query_bench_node_capacity(b, 8);
+}
+
+#[bench]
+fn hprtree_query_bench_node_capacity_32(b: &mut Bencher) {
+    query_bench_node_capacity(b, 32);
+}
+
+#[bench]
+fn hprtree_query_bench_node_capacity_64(b: &mut Bencher) {
+    query_bench_node_capacity(b, 64);
+}
+
+#[bench]
+fn hprtree_join_within_distance_bench(b: &mut Bencher) {
+    let pings = build_bench_hprtree(2);
+    let pois = build_bench_hprtree(1);
+    b.iter(|| {
+        join_within_distance(&pings, &pois, 0.5f64).len()
+    });
+}
@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use arc_swap::ArcSwap;
+
+use crate::{
+    BBox, Coordinate, CoordinateType, HPRTree, HPRTreeBuilder, ItemId, SpatiallyIndexableExtent,
+};
+
+/// The elements inserted and the ids removed since the last publish
+struct Pending<T> {
+    items: Vec<T>,
+    ids: Vec<usize>,
+    removed: Vec<usize>,
+    next_id: usize,
+}
+
+/// A spatial index shared between threads, readers load the current tree without locking while writers batch inserts and publish a freshly built tree
+///
+/// Readers get an `Arc` of the current tree from [snapshot](#method.snapshot), which is never modified and stays valid (and unchanged) for as long as it is held, even when newer trees are published in the meantime. Inserted elements only show up in snapshots loaded after the next [publish](#method.publish), which builds a new tree next to the current one and swaps it in atomically, so readers never observe a partially built tree
+///
+/// ```
+/// use hprtree::{BBox, ConcurrentHPRTree, Point};
+///
+/// let index = ConcurrentHPRTree::new();
+/// let id = index.insert(Point { x: 1f32, y: 1f32 });
+/// let before = index.snapshot();
+/// assert!(index.publish());
+///
+/// let query_env = BBox::new(0f32, 0f32, 2f32, 2f32);
+/// assert!(before.count(&query_env) == 0);
+/// assert!(index.snapshot().count(&query_env) == 1);
+/// assert!(index.snapshot().get(id).is_some());
+/// ```
+pub struct ConcurrentHPRTree<T, C = CoordinateType>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    current: ArcSwap<HPRTree<T, C>>,
+    pending: Mutex<Pending<T>>,
+    // held while building a new tree, so concurrent publishes do not build on the same tree and drop each others elements
+    publishing: Mutex<()>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // the guarded state stays consistent even if a writer panicked while holding the lock
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T, C> Default for ConcurrentHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C> ConcurrentHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Creates a new empty index, use [From] with an empty tree to set the node capacity and hilbert level
+    pub fn new() -> Self {
+        Self::from(HPRTreeBuilder::new(0).with_ids().build())
+    }
+
+    /// Returns the current tree without locking, it does not change when newer trees are published
+    pub fn snapshot(&self) -> Arc<HPRTree<T, C>> {
+        self.current.load_full()
+    }
+
+    /// Queues an element for the next [publish](#method.publish), the returned id identifies it in all trees published from then on
+    pub fn insert(&self, item: T) -> ItemId {
+        self.insert_with(|_| item)
+    }
+
+    /// Queues the element created from its id for the next [publish](#method.publish), for elements which keep their own id
+    pub fn insert_with(&self, create: impl FnOnce(ItemId) -> T) -> ItemId {
+        let mut pending = lock(&self.pending);
+        let id = ItemId(pending.next_id);
+        let item = create(id);
+        pending.next_id += 1;
+        pending.items.push(item);
+        pending.ids.push(id.0);
+        id
+    }
+
+    /// Queues the removal of an element for the next [publish](#method.publish), returning false if the id was never handed out
+    ///
+    /// Elements inserted since the last publish can be removed as well, removing an id twice has no further effect
+    pub fn remove(&self, id: ItemId) -> bool {
+        let mut pending = lock(&self.pending);
+        if id.0 >= pending.next_id {
+            return false;
+        }
+        pending.removed.push(id.0);
+        true
+    }
+
+    /// Returns the number of inserted elements waiting for the next publish
+    pub fn pending_len(&self) -> usize {
+        lock(&self.pending).items.len()
+    }
+
+    /// Returns the number of queued removals waiting for the next publish
+    pub fn pending_removals(&self) -> usize {
+        lock(&self.pending).removed.len()
+    }
+
+    /// Counts the elements of the current tree within the bbox without cloning them
+    pub fn count(&self, query_env: &BBox<C>) -> usize {
+        self.current.load().count(query_env)
+    }
+}
+
+impl<T, C> ConcurrentHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C> + Clone,
+    C: Coordinate,
+{
+    /// Builds a new tree from the current one, the queued elements and the queued removals and swaps it in atomically, returning whether there was anything to publish
+    ///
+    /// The elements of the current tree are cloned as readers may still hold it, inserts are not blocked while the new tree is built and end up in the publish after
+    pub fn publish(&self) -> bool {
+        let _publishing = lock(&self.publishing);
+        let (items, ids, mut removed) = {
+            let mut pending = lock(&self.pending);
+            (
+                std::mem::take(&mut pending.items),
+                std::mem::take(&mut pending.ids),
+                std::mem::take(&mut pending.removed),
+            )
+        };
+        if items.is_empty() && removed.is_empty() {
+            return false;
+        }
+        removed.sort_unstable();
+        let tree = self.current.load_full().rebuild_with(items, ids, &removed);
+        self.current.store(Arc::new(tree));
+        true
+    }
+
+    /// Queries the current tree by bounding box returning a Vec of clones of the found elements
+    pub fn query(&self, query_env: &BBox<C>) -> Vec<T> {
+        self.current.load().query(query_env)
+    }
+}
+
+impl<T, C> From<HPRTree<T, C>> for ConcurrentHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Uses a built tree as the first snapshot, later trees are built with its node capacity and hilbert level and elements keep their ids (or get the ids of their positions if it was built without ids)
+    fn from(tree: HPRTree<T, C>) -> Self {
+        let tree = tree.with_position_ids();
+        ConcurrentHPRTree {
+            pending: Mutex::new(Pending {
+                items: Vec::new(),
+                ids: Vec::new(),
+                removed: Vec::new(),
+                next_id: tree.id_count(),
+            }),
+            current: ArcSwap::from_pointee(tree),
+            publishing: Mutex::new(()),
+        }
+    }
+}
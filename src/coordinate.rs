@@ -0,0 +1,89 @@
+use std::fmt::Debug;
+
+/// Trait for the scalar type coordinates are stored as, implemented for f32, f64, i32 and i64
+///
+/// Everything that does not stay within the coordinate type (distances, areas, the hilbert quantisation) is computed in f64
+pub trait Coordinate: Copy + PartialOrd + Debug {
+    /// The smallest representable value
+    const MIN: Self;
+    /// The largest representable value
+    const MAX: Self;
+
+    /// Converts the coordinate to f64, which may lose precision for very large integers
+    fn to_f64(self) -> f64;
+
+    /// Converts from f64, rounding to the nearest value (and saturating) for integers
+    fn from_f64(value: f64) -> Self;
+
+    /// Returns the smaller of two coordinates
+    fn min(self, other: Self) -> Self;
+
+    /// Returns the larger of two coordinates
+    fn max(self, other: Self) -> Self;
+
+    /// Returns `self - other`, saturating instead of overflowing for integers
+    fn saturating_sub(self, other: Self) -> Self;
+}
+
+macro_rules! impl_float_coordinate {
+    ($t:ty) => {
+        impl Coordinate for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value as $t
+            }
+
+            fn min(self, other: Self) -> Self {
+                <$t>::min(self, other)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+
+            fn saturating_sub(self, other: Self) -> Self {
+                self - other
+            }
+        }
+    };
+}
+
+macro_rules! impl_integer_coordinate {
+    ($t:ty) => {
+        impl Coordinate for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value.round() as $t
+            }
+
+            fn min(self, other: Self) -> Self {
+                Ord::min(self, other)
+            }
+
+            fn max(self, other: Self) -> Self {
+                Ord::max(self, other)
+            }
+
+            fn saturating_sub(self, other: Self) -> Self {
+                <$t>::saturating_sub(self, other)
+            }
+        }
+    };
+}
+
+impl_float_coordinate!(f32);
+impl_float_coordinate!(f64);
+impl_integer_coordinate!(i32);
+impl_integer_coordinate!(i64);
@@ -0,0 +1,601 @@
+use std::{
+    marker::PhantomData,
+    ops::{ControlFlow, Range},
+};
+
+use crate::{
+    distance_to_item, BBox, Coordinate, CoordinateType, DistanceMetric, Euclidean, HPRTree,
+    HPRTreeBuilder, ItemId, Point, SpatiallyIndexableExtent, DEFAULT_HILBERT_LEVEL,
+    DEFAULT_NODE_CAPACITY, MAX_HILBERT_LEVEL,
+};
+
+/// The default number of elements a [DynamicHPRTree] keeps unsorted before packing them into a run
+pub const DEFAULT_BUFFER_CAPACITY: usize = 256;
+/// The default size ratio between neighbouring runs of a [DynamicHPRTree], below which they are merged
+pub const DEFAULT_MERGE_RATIO: usize = 4;
+
+/// A spatial index which supports inserting elements after it has been built
+///
+/// New elements are collected in a small unsorted buffer, which is packed into an [HPRTree] (a run) once it is full. Runs of similar size are merged by [maintain](#method.maintain), which is kept out of inserting so inserts never wait for a large run to be rebuilt. Queries fan out across all runs and the buffer, after [compact](#method.compact) there is only a single run left which is queried just like a static tree
+///
+/// Every element is given an [ItemId] by [insert](#method.insert), which it keeps through merges and compaction for [get](#method.get) and [remove](#method.remove)
+///
+/// ```
+/// use hprtree::{BBox, DynamicHPRTree, Point};
+///
+/// let mut index = DynamicHPRTree::new().with_buffer_capacity(4);
+/// for x in 0..100 {
+///     index.insert(Point { x: x as f32, y: 0f32 });
+/// }
+/// assert!(index.run_count() == 25);
+///
+/// while index.maintain() {}
+/// assert!(index.run_count() < 25);
+/// assert!(index.count(&BBox::new(9.5f32, -1f32, 19.5f32, 1f32)) == 10);
+///
+/// index.compact();
+/// assert!(index.run_count() == 1);
+/// assert!(index.count(&BBox::new(9.5f32, -1f32, 19.5f32, 1f32)) == 10);
+/// ```
+pub struct DynamicHPRTree<T, C = CoordinateType>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    runs: Vec<HPRTree<T, C>>,
+    buffer: Vec<T>,
+    buffer_ids: Vec<usize>,
+    next_id: usize,
+    extent: BBox<C>,
+    node_capacity: usize,
+    hilbert_level: u32,
+    buffer_capacity: usize,
+    merge_ratio: usize,
+    // counts the rebuilds and removals of existing runs, so a merge started before one of them is not swapped in
+    generation: usize,
+}
+
+/// The elements of the runs of a [DynamicHPRTree] due for merging as taken out by [start_merge](struct.DynamicHPRTree.html#method.start_merge), which are built into the merged run without access to the index
+pub struct PendingMerge<T, C = CoordinateType>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    items: Vec<T>,
+    ids: Vec<usize>,
+    runs: Range<usize>,
+    generation: usize,
+    node_capacity: usize,
+    hilbert_level: u32,
+    coordinate: PhantomData<C>,
+}
+
+/// A merged run built by [PendingMerge::build], to be swapped in by [finish_merge](struct.DynamicHPRTree.html#method.finish_merge)
+pub struct MergedRun<T, C = CoordinateType>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    run: HPRTree<T, C>,
+    runs: Range<usize>,
+    generation: usize,
+}
+
+impl<T, C> PendingMerge<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Builds the merged run, which takes as long as building a tree of all the elements of the merged runs
+    pub fn build(self) -> MergedRun<T, C> {
+        MergedRun {
+            run: build_run(self.items, self.ids, self.node_capacity, self.hilbert_level),
+            runs: self.runs,
+            generation: self.generation,
+        }
+    }
+
+    /// Returns the number of elements of the merged run
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns whether the merged run is empty
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+fn build_run<T, C>(
+    items: Vec<T>,
+    ids: Vec<usize>,
+    node_capacity: usize,
+    hilbert_level: u32,
+) -> HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    HPRTreeBuilder::from_entries(items, ids)
+        .with_node_capacity(node_capacity)
+        .with_hilbert_level(hilbert_level)
+        .build()
+}
+
+impl<T, C> Default for DynamicHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C> DynamicHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Creates a new empty index
+    pub fn new() -> Self {
+        DynamicHPRTree {
+            runs: Vec::new(),
+            buffer: Vec::new(),
+            buffer_ids: Vec::new(),
+            next_id: 0,
+            extent: BBox::default(),
+            node_capacity: DEFAULT_NODE_CAPACITY,
+            hilbert_level: DEFAULT_HILBERT_LEVEL,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            merge_ratio: DEFAULT_MERGE_RATIO,
+            generation: 0,
+        }
+    }
+
+    /// Sets the number of children per node of the runs, see [HPRTreeBuilder::with_node_capacity]
+    ///
+    /// Panics if the capacity is smaller than 2
+    pub fn with_node_capacity(mut self, node_capacity: usize) -> Self {
+        assert!(node_capacity >= 2, "the node capacity has to be at least 2");
+        self.node_capacity = node_capacity;
+        self
+    }
+
+    /// Sets the hilbert level the runs are sorted with, see [HPRTreeBuilder::with_hilbert_level]
+    ///
+    /// Panics if the level is not within 1..=16
+    pub fn with_hilbert_level(mut self, hilbert_level: u32) -> Self {
+        assert!(
+            (1..=MAX_HILBERT_LEVEL).contains(&hilbert_level),
+            "the hilbert level has to be within 1..=16"
+        );
+        self.hilbert_level = hilbert_level;
+        self
+    }
+
+    /// Sets the number of elements kept unsorted before they are packed into a run (256 by default), the buffer is scanned linearly by every query
+    ///
+    /// Panics if the capacity is 0
+    pub fn with_buffer_capacity(mut self, buffer_capacity: usize) -> Self {
+        assert!(
+            buffer_capacity >= 1,
+            "the buffer capacity has to be at least 1"
+        );
+        self.buffer_capacity = buffer_capacity;
+        self
+    }
+
+    /// Sets how many times larger a run has to be than the next newer one to not be merged with it (4 by default), a larger ratio means fewer merges but more runs to query
+    ///
+    /// Panics if the ratio is smaller than 2
+    pub fn with_merge_ratio(mut self, merge_ratio: usize) -> Self {
+        assert!(merge_ratio >= 2, "the merge ratio has to be at least 2");
+        self.merge_ratio = merge_ratio;
+        self
+    }
+
+    /// Inserts an element into the index, returning the id it can be found by with [get](#method.get) or removed by with [remove](#method.remove)
+    ///
+    /// The buffer is packed into a run once it is full, but runs are never merged here, see [maintain](#method.maintain)
+    pub fn insert(&mut self, item: T) -> ItemId {
+        let id = ItemId(self.next_id);
+        self.next_id += 1;
+        self.extent.expand_to_include(&item.bbox());
+        self.buffer.push(item);
+        self.buffer_ids.push(id.0);
+        if self.buffer.len() >= self.buffer_capacity {
+            self.flush();
+        }
+        id
+    }
+
+    /// Merges the newest runs into their predecessors for as long as those are less than the merge ratio times larger and compacts runs with too many removed elements, returning whether any run was rebuilt
+    ///
+    /// Nothing else merges or compacts runs, so the owner of the index has to call this, until it returns false, after every batch of inserts and removals or regularly from a background job. Left alone, every buffer capacity worth of inserts adds another run which all queries have to visit. Kept up with, there are only logarithmically many runs and each element is re-packed only logarithmically often. An index shared between threads can build the merged run without holding on to the index with [start_merge](#method.start_merge) and [finish_merge](#method.finish_merge) instead, leaving the compaction of runs to an occasional call of this
+    pub fn maintain(&mut self) -> bool {
+        if let Some(runs) = self.merge_range() {
+            let mut entries: (Vec<usize>, Vec<T>) = (Vec::new(), Vec::new());
+            for run in self.runs.drain(runs) {
+                entries.extend(run.into_entries());
+            }
+            let (ids, items) = entries;
+            let run = self.build_run(items, ids);
+            self.runs.push(run);
+            self.generation += 1;
+            return true;
+        }
+        let mut compacted = false;
+        for run in self.runs.iter_mut().filter(|run| run.needs_compaction()) {
+            run.compact();
+            compacted = true;
+        }
+        if compacted {
+            self.generation += 1;
+        }
+        compacted
+    }
+
+    /// Swaps in a merged run built from [start_merge](#method.start_merge) in place of the runs it was built from, returning whether it was swapped in
+    ///
+    /// Runs packed and elements removed in the meantime are taken into account, but if the merged runs have been rebuilt or dropped since (by [maintain](#method.maintain), [compact](#method.compact) or another merge) the merged run is dropped and false returned, the merge can simply be started again
+    pub fn finish_merge(&mut self, mut merged: MergedRun<T, C>) -> bool {
+        if merged.generation != self.generation {
+            return false;
+        }
+        // elements removed from the merged runs after the merge was started are still marked in them
+        for run in &self.runs[merged.runs.clone()] {
+            for id in run.removed_ids() {
+                merged.run.remove(id);
+            }
+        }
+        if merged.run.is_empty() {
+            self.runs.drain(merged.runs);
+        } else {
+            self.runs.splice(merged.runs, [merged.run]);
+        }
+        self.generation += 1;
+        true
+    }
+
+    /// Returns the element with the given id, if it has not been removed
+    ///
+    /// Looks through the buffer and then up the id in every run
+    pub fn get(&self, id: ItemId) -> Option<&T> {
+        if let Some(index) = self
+            .buffer_ids
+            .iter()
+            .position(|buffered| *buffered == id.0)
+        {
+            return Some(&self.buffer[index]);
+        }
+        self.runs.iter().find_map(|run| run.get(id))
+    }
+
+    /// Removes the element with the given id, returning whether there was one
+    ///
+    /// Elements in runs are only marked as removed until their run is merged or compacted by [maintain](#method.maintain)
+    pub fn remove(&mut self, id: ItemId) -> bool {
+        if let Some(index) = self
+            .buffer_ids
+            .iter()
+            .position(|buffered| *buffered == id.0)
+        {
+            self.buffer.swap_remove(index);
+            self.buffer_ids.swap_remove(index);
+            return true;
+        }
+        let Some(index) = self.runs.iter_mut().position(|run| run.remove(id)) else {
+            return false;
+        };
+        self.drop_if_empty(index);
+        true
+    }
+
+    /// Removes an element within the bbox which the predicate matches, returning whether there was one
+    ///
+    /// This is for elements whose id is not at hand, they are found by a query with the bbox which should be as small as possible (e.g. the bbox of the element). Elements in runs are only marked as removed until their run is merged or compacted by [maintain](#method.maintain)
+    pub fn remove_matching(
+        &mut self,
+        query_env: &BBox<C>,
+        mut predicate: impl FnMut(&T) -> bool,
+    ) -> bool {
+        if let Some(index) = self
+            .buffer
+            .iter()
+            .position(|item| query_env.intersects(&item.bbox()) && predicate(item))
+        {
+            self.buffer.swap_remove(index);
+            self.buffer_ids.swap_remove(index);
+            return true;
+        }
+        let Some(index) = self
+            .runs
+            .iter_mut()
+            .position(|run| run.remove_matching(query_env, &mut predicate))
+        else {
+            return false;
+        };
+        self.drop_if_empty(index);
+        true
+    }
+
+    fn drop_if_empty(&mut self, index: usize) {
+        if self.runs[index].is_empty() {
+            self.runs.remove(index);
+            self.generation += 1;
+        }
+    }
+
+    /// Packs the buffer and merges all runs into a single one without removed elements, after which queries are as fast as on a static tree
+    pub fn compact(&mut self) {
+        self.flush();
+        if self.runs.len() > 1 {
+            let mut entries: (Vec<usize>, Vec<T>) = (
+                Vec::with_capacity(self.len()),
+                Vec::with_capacity(self.len()),
+            );
+            for run in self.runs.drain(..) {
+                entries.extend(run.into_entries());
+            }
+            let (ids, items) = entries;
+            let run = self.build_run(items, ids);
+            self.runs.push(run);
+            self.generation += 1;
+        } else if let Some(run) = self.runs.first_mut().filter(|run| run.needs_compaction()) {
+            run.compact();
+            self.generation += 1;
+        }
+    }
+
+    /// Compacts the index and returns it as a static tree, in which the elements keep their ids
+    pub fn into_tree(mut self) -> HPRTree<T, C> {
+        self.compact();
+        let tree = match self.runs.pop() {
+            Some(run) => run,
+            None => self.build_run(Vec::new(), Vec::new()),
+        };
+        tree.with_id_count(self.next_id)
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let items = std::mem::take(&mut self.buffer);
+        let ids = std::mem::take(&mut self.buffer_ids);
+        let run = self.build_run(items, ids);
+        self.runs.push(run);
+    }
+
+    /// Returns the newest runs which are due to be merged into one
+    fn merge_range(&self) -> Option<Range<usize>> {
+        // the runs are ordered from oldest (largest) to newest (smallest), a new run is merged into its predecessor as long as that is not sufficiently larger
+        let end = self.runs.len();
+        let mut start = end.checked_sub(1)?;
+        let mut merged_len = self.runs[start].len();
+        while start > 0 && self.runs[start - 1].len() < self.merge_ratio * merged_len {
+            start -= 1;
+            merged_len += self.runs[start].len();
+        }
+        (start + 1 < end).then_some(start..end)
+    }
+
+    fn build_run(&self, items: Vec<T>, ids: Vec<usize>) -> HPRTree<T, C> {
+        build_run(items, ids, self.node_capacity, self.hilbert_level)
+    }
+
+    /// Queries all runs and the buffer by bounding box returning a lazy iterator over references to the found elements
+    pub fn query_iter<'a>(&'a self, query_env: &BBox<C>) -> impl Iterator<Item = &'a T> + 'a {
+        let query_env = query_env.clone();
+        let buffer_env = query_env.clone();
+        self.runs
+            .iter()
+            .flat_map(move |run| run.query_iter(&query_env))
+            .chain(
+                self.buffer
+                    .iter()
+                    .filter(move |item| buffer_env.intersects(&item.bbox())),
+            )
+    }
+
+    /// Queries all runs and the buffer by bounding box calling the visitor for each found element, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn query_visit<'a>(
+        &'a self,
+        query_env: &BBox<C>,
+        mut visitor: impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for run in &self.runs {
+            run.query_visit(query_env, &mut visitor)?;
+        }
+        for item in &self.buffer {
+            if query_env.intersects(&item.bbox()) {
+                visitor(item)?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Counts the elements within the bbox without cloning them
+    pub fn count(&self, query_env: &BBox<C>) -> usize {
+        self.runs
+            .iter()
+            .map(|run| run.count(query_env))
+            .sum::<usize>()
+            + self
+                .buffer
+                .iter()
+                .filter(|item| query_env.intersects(&item.bbox()))
+                .count()
+    }
+
+    /// Checks whether there is any element within the bbox, stopping at the first one found
+    pub fn any(&self, query_env: &BBox<C>) -> bool {
+        self.query_visit(query_env, |_| ControlFlow::Break(()))
+            .is_break()
+    }
+
+    /// Returns the number of elements in the index
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.len()).sum::<usize>() + self.buffer.len()
+    }
+
+    /// Returns whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the extent of the index
+    pub fn extent(&self) -> BBox<C> {
+        self.extent.clone()
+    }
+
+    /// Returns the id the next inserted element will be given, ids are handed out in increasing order and never again after their element was removed
+    pub fn next_id(&self) -> ItemId {
+        ItemId(self.next_id)
+    }
+
+    /// Returns the number of packed runs, which does not include the buffer
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+}
+
+impl<T, C> DynamicHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C> + Clone,
+    C: Coordinate,
+{
+    /// Takes clones of the elements of the runs [maintain](#method.maintain) would merge next, returning None if no runs are due for merging
+    ///
+    /// The merged run is built by [PendingMerge::build] without access to the index (e.g. outside of a lock guarding it) and swapped in by [finish_merge](#method.finish_merge), so neither queries nor inserts have to wait for it
+    ///
+    /// ```
+    /// use std::sync::RwLock;
+    /// use hprtree::{BBox, DynamicHPRTree, Point};
+    ///
+    /// let index = RwLock::new(DynamicHPRTree::new().with_buffer_capacity(4));
+    /// for x in 0..100 {
+    ///     index.write().unwrap().insert(Point { x: x as f32, y: 0f32 });
+    /// }
+    ///
+    /// loop {
+    ///     // the lock is only held for taking the elements out and for swapping the merged run in
+    ///     let Some(pending) = index.read().unwrap().start_merge() else {
+    ///         break;
+    ///     };
+    ///     let merged = pending.build();
+    ///     assert!(index.write().unwrap().finish_merge(merged));
+    /// }
+    /// let index = index.into_inner().unwrap();
+    /// assert!(index.run_count() < 25);
+    /// assert!(index.count(&BBox::new(9.5f32, -1f32, 19.5f32, 1f32)) == 10);
+    /// ```
+    pub fn start_merge(&self) -> Option<PendingMerge<T, C>> {
+        let runs = self.merge_range()?;
+        let (ids, items) = self.runs[runs.clone()]
+            .iter()
+            .flat_map(|run| run.iter_entries())
+            .map(|(id, item)| (id, item.clone()))
+            .unzip();
+        Some(PendingMerge {
+            items,
+            ids,
+            runs,
+            generation: self.generation,
+            node_capacity: self.node_capacity,
+            hilbert_level: self.hilbert_level,
+            coordinate: PhantomData,
+        })
+    }
+
+    /// Queries all runs and the buffer by bounding box returning a Vec of clones of the found elements
+    pub fn query(&self, query_env: &BBox<C>) -> Vec<T> {
+        let mut result = Vec::new();
+        self.query_with_list(query_env, &mut result);
+        result
+    }
+
+    /// Queries all runs and the buffer by bounding box appending clones of the found elements to the given Vec
+    pub fn query_with_list(&self, query_env: &BBox<C>, candidate_list: &mut Vec<T>) {
+        let _ = self.query_visit(query_env, |item| {
+            candidate_list.push(item.clone());
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// Queries all runs and the buffer for the elements within a given (euclidean) distance of a point, returning a Vec of the found elements
+    pub fn query_within_distance(&self, center: &Point<C>, radius: f64) -> Vec<T> {
+        self.query_within_distance_with_metric(center, radius, &Euclidean)
+    }
+
+    /// Queries all runs and the buffer for the elements within a given distance of a point as measured by the given metric, returning a Vec of the found elements
+    pub fn query_within_distance_with_metric(
+        &self,
+        center: &Point<C>,
+        radius: f64,
+        metric: &impl DistanceMetric,
+    ) -> Vec<T> {
+        let mut result = Vec::new();
+        for run in &self.runs {
+            result.extend(run.query_within_distance_with_metric(center, radius, metric));
+        }
+        result.extend(
+            self.buffer
+                .iter()
+                .filter(|item| distance_to_item(metric, center, &item.bbox()) <= radius)
+                .cloned(),
+        );
+        result
+    }
+
+    /// Finds the k elements closest to a point (by euclidean distance) across all runs and the buffer, returned in order of increasing distance
+    pub fn nearest_k(&self, point: &Point<C>, k: usize) -> Vec<T> {
+        self.nearest_k_with_metric(point, k, &Euclidean)
+    }
+
+    /// Finds the k elements closest to a point as measured by the given metric across all runs and the buffer, returned in order of increasing distance
+    pub fn nearest_k_with_metric(
+        &self,
+        point: &Point<C>,
+        k: usize,
+        metric: &impl DistanceMetric,
+    ) -> Vec<T> {
+        // the k closest elements of each run are candidates, only they are cloned
+        let mut candidates: Vec<(f64, &T)> = self
+            .runs
+            .iter()
+            .flat_map(|run| run.nearest_k_items(point, k, metric))
+            .chain(&self.buffer)
+            .map(|item| (distance_to_item(metric, point, &item.bbox()), item))
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        candidates
+            .into_iter()
+            .take(k)
+            .map(|(_, item)| item.clone())
+            .collect()
+    }
+}
+
+impl<T, C> From<HPRTree<T, C>> for DynamicHPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Uses a built tree as the first run, later runs are built with its node capacity and hilbert level
+    ///
+    /// The elements keep their ids if the tree was built with ids, otherwise they are given the ids of their positions in the tree
+    fn from(tree: HPRTree<T, C>) -> Self {
+        let tree = tree.with_position_ids();
+        let mut index = Self::new()
+            .with_node_capacity(tree.node_capacity())
+            .with_hilbert_level(tree.hilbert_level());
+        index.extent = tree.extent();
+        index.next_id = tree.id_count();
+        if !tree.is_empty() {
+            index.runs.push(tree);
+        }
+        index
+    }
+}
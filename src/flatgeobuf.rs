@@ -0,0 +1,279 @@
+use std::{
+    fmt::{self, Display},
+    io::{self, Write},
+    ops::{ControlFlow, Range},
+};
+
+use crate::{BBox, Coordinate};
+
+/// The size of a node in a FlatGeobuf index, four f64 for the bbox followed by a u64 offset
+pub const FLATGEOBUF_NODE_ITEM_SIZE: usize = 40;
+
+/// Returns the ranges of the levels of a FlatGeobuf index as indices into its nodes, starting with the items and ending with the root
+///
+/// The nodes are stored top-down, so the root is the first node and the items are the last ones. Returns None if the number of nodes does not fit in a usize, which only a corrupted header can cause
+fn flatgeobuf_level_bounds(num_items: usize, node_size: usize) -> Option<Vec<Range<usize>>> {
+    let mut n = num_items;
+    let mut level_num_nodes = vec![n];
+    loop {
+        n = n.div_ceil(node_size);
+        level_num_nodes.push(n);
+        if n <= 1 {
+            break;
+        }
+    }
+
+    let mut end = level_num_nodes
+        .iter()
+        .try_fold(0usize, |sum, size| sum.checked_add(*size))?;
+    let level_bounds = level_num_nodes
+        .iter()
+        .map(|size| {
+            let start = end - size;
+            let level = start..end;
+            end = start;
+            level
+        })
+        .collect();
+    Some(level_bounds)
+}
+
+/// Returns the number of bytes the index section of a FlatGeobuf file with the given number of features and index node size takes up
+///
+/// Returns None if the size does not fit in a usize, e.g. for a number of features read from a corrupted header
+pub fn flatgeobuf_index_size(num_items: usize, node_size: u16) -> Option<usize> {
+    if num_items == 0 || node_size < 2 {
+        return Some(0);
+    }
+    // the items are the last nodes, so their end is the number of nodes
+    let num_nodes = flatgeobuf_level_bounds(num_items, node_size as usize)?[0].end;
+    num_nodes.checked_mul(FLATGEOBUF_NODE_ITEM_SIZE)
+}
+
+fn write_node_item(writer: &mut impl Write, bbox: &BBox<f64>, offset: u64) -> io::Result<()> {
+    let mut buf = [0u8; FLATGEOBUF_NODE_ITEM_SIZE];
+    buf[0..8].copy_from_slice(&bbox.minx.to_le_bytes());
+    buf[8..16].copy_from_slice(&bbox.miny.to_le_bytes());
+    buf[16..24].copy_from_slice(&bbox.maxx.to_le_bytes());
+    buf[24..32].copy_from_slice(&bbox.maxy.to_le_bytes());
+    buf[32..40].copy_from_slice(&offset.to_le_bytes());
+    writer.write_all(&buf)
+}
+
+fn to_f64_bbox<C>(bbox: &BBox<C>) -> BBox<f64>
+where
+    C: Coordinate,
+{
+    BBox {
+        minx: bbox.minx.to_f64(),
+        miny: bbox.miny.to_f64(),
+        maxx: bbox.maxx.to_f64(),
+        maxy: bbox.maxy.to_f64(),
+    }
+}
+
+/// Writes the parts of a tree as a FlatGeobuf index section, shared by [HPRTree::write_flatgeobuf_index](struct.HPRTree.html#method.write_flatgeobuf_index) and [HPRTreeWrapping::write_flatgeobuf_index](struct.HPRTreeWrapping.html#method.write_flatgeobuf_index)
+///
+/// The layers of the tree are exactly the levels of the FlatGeobuf index above the items, only trees with fewer elements than the node capacity have no nodes while FlatGeobuf always has a root
+pub(crate) fn write_flatgeobuf_index<I, C>(
+    writer: &mut impl Write,
+    items: &[I],
+    mut item_parts: impl FnMut(&I) -> (BBox<C>, u64),
+    extent: &BBox<C>,
+    layer_start_index: &[usize],
+    node_bounds: &[BBox<C>],
+    node_capacity: usize,
+) -> io::Result<()>
+where
+    C: Coordinate,
+{
+    if items.is_empty() {
+        return Ok(());
+    }
+    if node_capacity > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "FlatGeobuf supports node capacities of up to 65535",
+        ));
+    }
+
+    // the nodes of a tree that fits in memory can always be counted
+    let level_bounds = flatgeobuf_level_bounds(items.len(), node_capacity).unwrap();
+    for level in (1..level_bounds.len()).rev() {
+        let child_level_start = level_bounds[level - 1].start;
+        for node_offset in 0..level_bounds[level].len() {
+            let bounds = if layer_start_index.is_empty() {
+                to_f64_bbox(extent)
+            } else {
+                to_f64_bbox(&node_bounds[layer_start_index[level - 1] + node_offset])
+            };
+            let first_child = child_level_start + node_offset * node_capacity;
+            write_node_item(writer, &bounds, first_child as u64)?;
+        }
+    }
+    for item in items {
+        let (bbox, offset) = item_parts(item);
+        write_node_item(writer, &to_f64_bbox(&bbox), offset)?;
+    }
+    Ok(())
+}
+
+/// The reason why bytes could not be read as a FlatGeobuf index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlatGeobufIndexError {
+    /// The node size is smaller than 2
+    NodeSize(u16),
+    /// There are fewer bytes than the index of the given number of features takes up
+    Length { expected: usize, found: usize },
+}
+
+impl Display for FlatGeobufIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeSize(node_size) => {
+                write!(
+                    f,
+                    "invalid index node size {node_size}, has to be at least 2"
+                )
+            }
+            Self::Length { expected, found } => {
+                write!(f, "expected {expected} bytes of index, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlatGeobufIndexError {}
+
+/// A feature found by searching a [FlatGeobufIndex]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlatGeobufSearchResult {
+    /// The byte offset of the feature within the feature section
+    pub offset: u64,
+    /// The index of the feature within the file
+    pub index: usize,
+}
+
+/// The packed hilbert r-tree index section of a FlatGeobuf file, searched directly on its bytes
+///
+/// The number of features and the index node size are taken from the header of the file
+pub struct FlatGeobufIndex<'a> {
+    bytes: &'a [u8],
+    node_size: usize,
+    level_bounds: Vec<Range<usize>>,
+}
+
+impl<'a> FlatGeobufIndex<'a> {
+    /// Reads the index section of a FlatGeobuf file with the given number of features and index node size, bytes after the index are ignored
+    pub fn new(
+        bytes: &'a [u8],
+        num_items: usize,
+        node_size: u16,
+    ) -> Result<Self, FlatGeobufIndexError> {
+        if node_size < 2 {
+            return Err(FlatGeobufIndexError::NodeSize(node_size));
+        }
+        let expected =
+            flatgeobuf_index_size(num_items, node_size).ok_or(FlatGeobufIndexError::Length {
+                expected: usize::MAX,
+                found: bytes.len(),
+            })?;
+        if bytes.len() < expected {
+            return Err(FlatGeobufIndexError::Length {
+                expected,
+                found: bytes.len(),
+            });
+        }
+        Ok(Self {
+            bytes: &bytes[..expected],
+            node_size: node_size as usize,
+            level_bounds: if num_items == 0 {
+                Vec::new()
+            } else {
+                // the size of the levels was already checked above
+                flatgeobuf_level_bounds(num_items, node_size as usize).unwrap()
+            },
+        })
+    }
+
+    /// Returns the number of features in the index
+    pub fn len(&self) -> usize {
+        self.level_bounds.first().map_or(0, |items| items.len())
+    }
+
+    /// Returns whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes the index takes up
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn node(&self, node_index: usize) -> (BBox<f64>, u64) {
+        let bytes = &self.bytes[node_index * FLATGEOBUF_NODE_ITEM_SIZE..];
+        let read_f64 =
+            |offset: usize| f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        (
+            BBox {
+                minx: read_f64(0),
+                miny: read_f64(8),
+                maxx: read_f64(16),
+                maxy: read_f64(24),
+            },
+            u64::from_le_bytes(bytes[32..40].try_into().unwrap()),
+        )
+    }
+
+    fn search_node(
+        &self,
+        level: usize,
+        node_offset: usize,
+        query_env: &BBox<f64>,
+        visitor: &mut impl FnMut(FlatGeobufSearchResult) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let level_bounds = &self.level_bounds[level];
+        let (bounds, offset) = self.node(level_bounds.start + node_offset);
+        if !query_env.intersects(&bounds) {
+            return ControlFlow::Continue(());
+        }
+        if level == 0 {
+            return visitor(FlatGeobufSearchResult {
+                offset,
+                index: node_offset,
+            });
+        }
+
+        // the offset of an inner node points at its first child, which always follows from the layout, so it is computed instead of trusting the file
+        let child_node_offset = node_offset * self.node_size;
+        let child_node_end =
+            (child_node_offset + self.node_size).min(self.level_bounds[level - 1].len());
+        for child in child_node_offset..child_node_end {
+            self.search_node(level - 1, child, query_env, visitor)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Searches the index calling the visitor for each feature whose bbox intersects the query envelope in the order of the features, the search stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn search_visit(
+        &self,
+        query_env: &BBox<f64>,
+        mut visitor: impl FnMut(FlatGeobufSearchResult) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        match self.level_bounds.len() {
+            0 => ControlFlow::Continue(()),
+            levels => self.search_node(levels - 1, 0, query_env, &mut visitor),
+        }
+    }
+
+    /// Searches the index returning the features whose bbox intersects the query envelope in the order of the features
+    pub fn search(&self, query_env: &BBox<f64>) -> Vec<FlatGeobufSearchResult> {
+        let mut result = Vec::new();
+        let _ = self.search_visit(query_env, |item| {
+            result.push(item);
+            ControlFlow::Continue(())
+        });
+        result
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt::{self, Display};
+
+use ::geojson::{Feature, FeatureCollection, JsonObject, Position, Value};
+
+use crate::{
+    BBox, Coordinate, CoordinateType, HPRTreeBuilder, HPRTreeWrappingBuilder, Point,
+    SpatiallyIndexableExtent,
+};
+
+/// The reason why a GeoJSON feature could not be indexed, each variant carries the index of the feature within its collection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GeoJsonError {
+    /// The feature has no geometry
+    MissingGeometry(usize),
+    /// The geometry has no positions (e.g. an empty MultiPoint), so it has no bounding box
+    EmptyGeometry(usize),
+    /// A position has fewer than two or non-finite coordinates
+    InvalidPosition(usize),
+    /// The geometry is not a Point, which is the only geometry an [HPRTreeWrapping](struct.HPRTreeWrapping.html) indexes
+    NotAPoint {
+        feature: usize,
+        geometry: &'static str,
+    },
+}
+
+impl Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingGeometry(feature) => write!(f, "feature {feature} has no geometry"),
+            Self::EmptyGeometry(feature) => {
+                write!(f, "the geometry of feature {feature} has no positions")
+            }
+            Self::InvalidPosition(feature) => write!(
+                f,
+                "feature {feature} has a position with fewer than two or non-finite coordinates"
+            ),
+            Self::NotAPoint { feature, geometry } => write!(
+                f,
+                "feature {feature} is a {geometry}, only Point geometries can be indexed by position"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GeoJsonError {}
+
+/// A GeoJSON feature as an element of a tree, indexed by the bounding box of its geometry
+///
+/// The feature is kept as it was read (properties, id and foreign members included), so query results can be written back as GeoJSON with [to_feature_collection]
+///
+/// ```
+/// use hprtree::{to_feature_collection, BBox, GeoJsonFeature, HPRTreeBuilder};
+///
+/// let collection = r#"{"type": "FeatureCollection", "features": [
+///     {"type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 1]}, "properties": {"name": "a"}},
+///     {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[5, 5], [9, 8]]}, "properties": {"name": "b"}}
+/// ]}"#;
+///
+/// let index = HPRTreeBuilder::<GeoJsonFeature>::from_geojson(collection.parse().unwrap())
+///     .unwrap()
+///     .build();
+/// let result = index.query(&BBox::new(6f32, 6f32, 7f32, 7f32));
+/// assert!(result[0].properties().unwrap()["name"] == "b");
+///
+/// let geojson = to_feature_collection(&result).to_string();
+/// assert!(geojson.contains("LineString"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct GeoJsonFeature<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    feature: Feature,
+    bbox: BBox<C>,
+}
+
+fn expand_to_include_positions<'a, C>(
+    bbox: &mut BBox<C>,
+    positions: impl IntoIterator<Item = &'a Position>,
+    feature_index: usize,
+) -> Result<(), GeoJsonError>
+where
+    C: Coordinate,
+{
+    for position in positions {
+        let [x, y, ..] = position[..] else {
+            return Err(GeoJsonError::InvalidPosition(feature_index));
+        };
+        if !x.is_finite() || !y.is_finite() {
+            return Err(GeoJsonError::InvalidPosition(feature_index));
+        }
+        bbox.expand_to_include_point(&Point {
+            x: C::from_f64(x),
+            y: C::from_f64(y),
+        });
+    }
+    Ok(())
+}
+
+fn expand_to_include_value<C>(
+    bbox: &mut BBox<C>,
+    value: &Value,
+    feature_index: usize,
+) -> Result<(), GeoJsonError>
+where
+    C: Coordinate,
+{
+    match value {
+        Value::Point(position) => expand_to_include_positions(bbox, [position], feature_index),
+        Value::MultiPoint(positions) | Value::LineString(positions) => {
+            expand_to_include_positions(bbox, positions, feature_index)
+        }
+        Value::MultiLineString(lines) | Value::Polygon(lines) => {
+            expand_to_include_positions(bbox, lines.iter().flatten(), feature_index)
+        }
+        Value::MultiPolygon(polygons) => {
+            expand_to_include_positions(bbox, polygons.iter().flatten().flatten(), feature_index)
+        }
+        Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .try_for_each(|geometry| expand_to_include_value(bbox, &geometry.value, feature_index)),
+    }
+}
+
+impl<C> GeoJsonFeature<C>
+where
+    C: Coordinate,
+{
+    fn new(feature: Feature, feature_index: usize) -> Result<Self, GeoJsonError> {
+        let geometry = feature
+            .geometry
+            .as_ref()
+            .ok_or(GeoJsonError::MissingGeometry(feature_index))?;
+        let mut bbox = BBox::default();
+        expand_to_include_value(&mut bbox, &geometry.value, feature_index)?;
+        // the default bbox is inverted, it stays that way if there were no positions at all
+        if bbox.minx > bbox.maxx {
+            return Err(GeoJsonError::EmptyGeometry(feature_index));
+        }
+        Ok(GeoJsonFeature { feature, bbox })
+    }
+
+    /// Returns the feature as it was read
+    pub fn feature(&self) -> &Feature {
+        &self.feature
+    }
+
+    /// Returns the properties of the feature
+    pub fn properties(&self) -> Option<&JsonObject> {
+        self.feature.properties.as_ref()
+    }
+
+    /// Takes the feature out of the element
+    pub fn into_feature(self) -> Feature {
+        self.feature
+    }
+}
+
+// implemented per coordinate type like for BBox, a generic impl would conflict with the blanket impl for SpatiallyIndexable
+macro_rules! impl_geojson_feature_extent {
+    ($($t:ty),*) => {
+        $(
+            impl SpatiallyIndexableExtent<$t> for GeoJsonFeature<$t> {
+                fn bbox(&self) -> BBox<$t> {
+                    self.bbox.clone()
+                }
+            }
+        )*
+    };
+}
+
+impl_geojson_feature_extent!(f32, f64, i32, i64);
+
+impl<C> From<GeoJsonFeature<C>> for Feature
+where
+    C: Coordinate,
+{
+    fn from(feature: GeoJsonFeature<C>) -> Self {
+        feature.feature
+    }
+}
+
+/// Writes features (e.g. the results of a query) as a FeatureCollection
+pub fn to_feature_collection<'a, C>(
+    features: impl IntoIterator<Item = &'a GeoJsonFeature<C>>,
+) -> FeatureCollection
+where
+    C: Coordinate + 'a,
+{
+    features
+        .into_iter()
+        .map(|feature| feature.feature.clone())
+        .collect()
+}
+
+impl<C> HPRTreeBuilder<GeoJsonFeature<C>, C>
+where
+    C: Coordinate,
+    GeoJsonFeature<C>: SpatiallyIndexableExtent<C>,
+{
+    /// Creates a tree builder from the features of a FeatureCollection, every geometry is indexed by its bounding box
+    ///
+    /// Fails on the first feature without a geometry or with invalid positions
+    pub fn from_geojson(collection: FeatureCollection) -> Result<Self, GeoJsonError> {
+        let items = collection
+            .features
+            .into_iter()
+            .enumerate()
+            .map(|(feature_index, feature)| GeoJsonFeature::new(feature, feature_index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_vec(items))
+    }
+}
+
+impl<C> HPRTreeWrappingBuilder<GeoJsonFeature<C>, C>
+where
+    C: Coordinate,
+{
+    /// Creates a tree builder from the features of a FeatureCollection, each one indexed by its Point geometry
+    ///
+    /// Fails on the first feature that is not a Point, use [HPRTreeBuilder::from_geojson](struct.HPRTreeBuilder.html#method.from_geojson) for other geometries
+    pub fn from_geojson(collection: FeatureCollection) -> Result<Self, GeoJsonError> {
+        let mut builder = Self::new(collection.features.len());
+        for (feature_index, feature) in collection.features.into_iter().enumerate() {
+            let feature = GeoJsonFeature::new(feature, feature_index)?;
+            if let Some(geometry) = &feature.feature.geometry {
+                if !matches!(geometry.value, Value::Point(_)) {
+                    return Err(GeoJsonError::NotAPoint {
+                        feature: feature_index,
+                        geometry: geometry.value.type_name(),
+                    });
+                }
+            }
+            let point = Point {
+                x: feature.bbox.minx,
+                y: feature.bbox.miny,
+            };
+            builder.insert(feature, point);
+        }
+        Ok(builder)
+    }
+}
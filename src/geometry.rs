@@ -0,0 +1,109 @@
+use crate::{BBox, Coordinate, CoordinateType, Point, Polygon};
+
+/// A geometry read from or written as WKT (see [from_wkt](enum.Geometry.html#method.from_wkt)) or WKB (see [from_wkb](enum.Geometry.html#method.from_wkb)), in terms of the types used for querying the index
+#[derive(Clone, Debug)]
+pub enum Geometry<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    Point(Point<C>),
+    Polygon(Polygon<C>),
+    /// The polygons of a MULTIPOLYGON, query the index with all of them at once by [query_multi_polygon](struct.HPRTree.html#method.query_multi_polygon)
+    MultiPolygon(Vec<Polygon<C>>),
+    /// An axis aligned rectangle, read from `ENVELOPE(minx, maxx, maxy, miny)` or `BOX(minx miny, maxx maxy)` and written as a POLYGON
+    Envelope(BBox<C>),
+}
+
+impl<C> Geometry<C>
+where
+    C: Coordinate,
+{
+    /// Returns the name of the geometry type as written in WKT
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Point(_) => "POINT",
+            Self::Polygon(_) => "POLYGON",
+            Self::MultiPolygon(_) => "MULTIPOLYGON",
+            Self::Envelope(_) => "ENVELOPE",
+        }
+    }
+
+    /// Returns the bbox of the geometry, e.g. to query the index with the envelope of any geometry
+    pub fn bbox(&self) -> BBox<C> {
+        match self {
+            Self::Point(point) => BBox::new(point.x, point.y, point.x, point.y),
+            Self::Polygon(polygon) => polygon.bbox(),
+            Self::MultiPolygon(polygons) => {
+                let mut bbox = BBox::default();
+                for polygon in polygons {
+                    bbox.expand_to_include(&polygon.bbox());
+                }
+                bbox
+            }
+            Self::Envelope(bbox) => bbox.clone(),
+        }
+    }
+
+    /// Returns the rings of each polygon of the geometry as they are written, exterior first and closed
+    ///
+    /// A point has no rings, an envelope is a single polygon with a counter-clockwise exterior
+    pub(crate) fn polygon_rings(&self) -> Vec<Vec<Vec<Point<C>>>> {
+        fn closed<C: Coordinate>(ring: &[Point<C>]) -> Vec<Point<C>> {
+            let mut ring = ring.to_vec();
+            if let (Some(first), Some(last)) = (ring.first(), ring.last()) {
+                if first.x != last.x || first.y != last.y {
+                    ring.push(first.clone());
+                }
+            }
+            ring
+        }
+        fn rings<C: Coordinate>(polygon: &Polygon<C>) -> Vec<Vec<Point<C>>> {
+            std::iter::once(polygon.exterior())
+                .chain(polygon.holes().iter().map(Vec::as_slice))
+                .map(closed)
+                .collect()
+        }
+        match self {
+            Self::Point(_) => Vec::new(),
+            Self::Polygon(polygon) => vec![rings(polygon)],
+            Self::MultiPolygon(polygons) => polygons.iter().map(rings).collect(),
+            Self::Envelope(bbox) => {
+                let corner = |x, y| Point { x, y };
+                vec![vec![vec![
+                    corner(bbox.minx, bbox.miny),
+                    corner(bbox.maxx, bbox.miny),
+                    corner(bbox.maxx, bbox.maxy),
+                    corner(bbox.minx, bbox.maxy),
+                    corner(bbox.minx, bbox.miny),
+                ]]]
+            }
+        }
+    }
+}
+
+impl<C> From<Point<C>> for Geometry<C>
+where
+    C: Coordinate,
+{
+    fn from(point: Point<C>) -> Self {
+        Self::Point(point)
+    }
+}
+
+impl<C> From<Polygon<C>> for Geometry<C>
+where
+    C: Coordinate,
+{
+    fn from(polygon: Polygon<C>) -> Self {
+        Self::Polygon(polygon)
+    }
+}
+
+impl<C> From<BBox<C>> for Geometry<C>
+where
+    C: Coordinate,
+{
+    fn from(bbox: BBox<C>) -> Self {
+        Self::Envelope(bbox)
+    }
+}
@@ -1,28 +1,68 @@
-use std::mem::size_of;
+use std::{
+    collections::BinaryHeap,
+    io::{self, Write},
+    marker::PhantomData,
+    mem::{self, size_of},
+    ops::{ControlFlow, Range},
+};
 
-use crate::{get_layer_size, hilbert_xy_to_index, BBox, SpatiallyIndexable, H, NODE_CAPACITY};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "serde")]
+use crate::{validate_ids, validate_tree, InvalidTreeError, TreeData};
+
+use crate::{
+    compute_layer_start_indices, distance_to_item, expand_ancestor_bounds, find_position,
+    get_layer_size, get_subtree_item_range, reorder, sort_positions_by_id, write_flatgeobuf_index,
+    write_mapped, BBox, Coordinate, CoordinateType, DistanceMetric, DistanceQuery, Euclidean,
+    HilbertGrid, ItemId, JoinSide, MappableCoordinate, MappableItem, MultiPolygonQuery,
+    NearestCandidate, NearestCandidateKind, Point, Polygon, PredicateQuery, QueryShape,
+    SpatialPredicate, SpatiallyIndexableExtent, SpatiallyMovable, Tombstones, WithIds, WithoutIds,
+    COMPACTION_THRESHOLD, DEFAULT_HILBERT_LEVEL, DEFAULT_NODE_CAPACITY, MAX_HILBERT_LEVEL,
+};
 
 /// The builder for the spatial index, start here
+///
+/// The last type parameter tells whether the builder hands out ids for the inserted elements, which only a builder turned [with_ids](#method.with_ids) does
 #[derive(Clone)]
-pub struct HPRTreeBuilder<T>
+pub struct HPRTreeBuilder<T, C = CoordinateType, Ids = WithoutIds>
 where
-    T: SpatiallyIndexable,
-    T: Clone,
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
 {
     items: Vec<T>,
-    extent: BBox,
+    ids: Vec<usize>,
+    // also set by the rebuilds within the crate, which push the ids of the elements they take over directly
+    keep_ids: bool,
+    extent: BBox<C>,
+    node_capacity: usize,
+    hilbert_level: u32,
+    id_mode: PhantomData<Ids>,
 }
 
 /// The spatial index itself
-pub struct HPRTree<T>
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "TreeData<T, C>"))]
+pub struct HPRTree<T, C = CoordinateType>
 where
-    T: SpatiallyIndexable,
-    T: Clone,
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
 {
     items: Vec<T>,
-    extent: BBox,
+    ids: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    positions: Vec<usize>,
+    id_count: usize,
+    tombstones: Tombstones,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    removed: usize,
+    stale: usize,
+    extent: BBox<C>,
     layer_start_index: Vec<usize>,
-    node_bounds: Vec<BBox>,
+    node_bounds: Vec<BBox<C>>,
+    node_capacity: usize,
+    hilbert_level: u32,
 }
 
 /// Example usage:
@@ -74,35 +114,127 @@ where
 ///     // there are absolutely no guarantees regarding ordering though
 /// }
 /// ```
-
-impl<T> HPRTreeBuilder<T>
+impl<T, C> HPRTreeBuilder<T, C>
 where
-    T: SpatiallyIndexable,
-    T: Clone,
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
 {
     /// Creates a new tree builder with base capacity
     pub fn new(size: usize) -> Self {
         HPRTreeBuilder {
             items: Vec::with_capacity(size),
+            ids: Vec::new(),
+            keep_ids: false,
             extent: BBox::default(),
+            node_capacity: DEFAULT_NODE_CAPACITY,
+            hilbert_level: DEFAULT_HILBERT_LEVEL,
+            id_mode: PhantomData,
+        }
+    }
+
+    /// Creates a new tree builder from the elements of a Vec, which is taken over as is while the extent is computed in a single pass
+    pub fn from_vec(items: Vec<T>) -> Self {
+        let mut extent = BBox::default();
+        for item in &items {
+            extent.expand_to_include(&item.bbox());
+        }
+        HPRTreeBuilder {
+            items,
+            ids: Vec::new(),
+            keep_ids: false,
+            extent,
+            node_capacity: DEFAULT_NODE_CAPACITY,
+            hilbert_level: DEFAULT_HILBERT_LEVEL,
+            id_mode: PhantomData,
+        }
+    }
+
+    /// Keeps ids for the elements in the built tree, which is needed to [get](struct.HPRTree.html#method.get), [remove](struct.HPRTree.html#method.remove) or [update](struct.HPRTree.html#method.update) elements by id and takes two more `usize` per element, [insert](#method.insert-1) then returns the id of each element
+    ///
+    /// Elements inserted before are given the ids of their insertion order, as long as they have not been sorted yet
+    pub fn with_ids(self) -> HPRTreeBuilder<T, C, WithIds> {
+        HPRTreeBuilder {
+            ids: (0..self.items.len()).collect(),
+            items: self.items,
+            keep_ids: true,
+            extent: self.extent,
+            node_capacity: self.node_capacity,
+            hilbert_level: self.hilbert_level,
+            id_mode: PhantomData,
         }
     }
 
     /// Inserts an element into the index
     pub fn insert(&mut self, item: T) {
-        self.extent.expand_to_include_spatially_indexable(&item);
+        self.push(item);
+    }
+}
+
+impl<T, C> HPRTreeBuilder<T, C, WithIds>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Creates a new tree builder from elements and the ids they have been given before (e.g. in the runs of a [DynamicHPRTree](crate::DynamicHPRTree))
+    pub(crate) fn from_entries(items: Vec<T>, ids: Vec<usize>) -> Self {
+        let HPRTreeBuilder { items, extent, .. } = HPRTreeBuilder::from_vec(items);
+        HPRTreeBuilder {
+            items,
+            ids,
+            keep_ids: true,
+            extent,
+            node_capacity: DEFAULT_NODE_CAPACITY,
+            hilbert_level: DEFAULT_HILBERT_LEVEL,
+            id_mode: PhantomData,
+        }
+    }
+
+    /// Inserts an element into the index, the returned id identifies it in the built tree (e.g. for [remove](struct.HPRTree.html#method.remove))
+    pub fn insert(&mut self, item: T) -> ItemId {
+        self.push(item)
+    }
+}
+
+impl<T, C, Ids> HPRTreeBuilder<T, C, Ids>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Sets the number of children per node (16 by default), larger nodes mean a flatter tree but more elements being tested per visited node
+    ///
+    /// Panics if the capacity is smaller than 2
+    pub fn with_node_capacity(mut self, node_capacity: usize) -> Self {
+        assert!(node_capacity >= 2, "the node capacity has to be at least 2");
+        self.node_capacity = node_capacity;
+        self
+    }
+
+    /// Sets the number of bits per axis used for sorting the elements along the hilbert curve (12 by default), dense data profits from a higher level as fewer elements share a cell
+    ///
+    /// Panics if the level is not within 1..=16
+    pub fn with_hilbert_level(mut self, hilbert_level: u32) -> Self {
+        assert!(
+            (1..=MAX_HILBERT_LEVEL).contains(&hilbert_level),
+            "the hilbert level has to be within 1..=16"
+        );
+        self.hilbert_level = hilbert_level;
+        self
+    }
+
+    fn push(&mut self, item: T) -> ItemId {
+        let id = ItemId(self.items.len());
+        self.extent.expand_to_include(&item.bbox());
         self.items.push(item);
+        if self.keep_ids {
+            self.ids.push(id.0);
+        }
+        id
     }
 
     /// Sorts the data, builds the index and transfers the builders state into an HPRTree which is then returned. If [sort_items](#method.sort_items) has been called before, prefer [build_sorted](#method.build_sorted) instead
-    pub fn build(mut self) -> HPRTree<T> {
-        if self.items.len() < NODE_CAPACITY {
-            return HPRTree {
-                items: self.items,
-                extent: self.extent,
-                layer_start_index: Vec::new(),
-                node_bounds: Vec::new(),
-            };
+    pub fn build(mut self) -> HPRTree<T, C> {
+        if self.items.len() < self.node_capacity {
+            return self.finish(Vec::new(), Vec::new());
         }
 
         self.sort_items();
@@ -112,49 +244,53 @@ where
 
     /// Sorts the contained data (in preparation for [build_sorted](#method.build_sorted))
     pub fn sort_items(&mut self) {
-        let stride_x = if self.extent.width() != 0f32 {
-            self.extent.width() / H as f32
-        } else {
-            1f32
-        };
-        let stride_y = if self.extent.height() != 0f32 {
-            self.extent.height() / H as f32
-        } else {
-            1f32
-        };
-
-        let extent_min = self.extent.minx.min(self.extent.miny);
+        let grid = HilbertGrid::new(&self.extent, self.hilbert_level);
 
-        self.items.sort_by_cached_key(|pt| {
-            let x: u32 = ((pt.x() - extent_min) / stride_x).trunc() as u32;
-            let y: u32 = ((pt.y() - extent_min) / stride_y).trunc() as u32;
-            hilbert_xy_to_index(x, y)
-        });
+        // the (key, position) pairs are sorted instead of the elements to keep the ids in step, they are unique so the unstable sort still gives a deterministic order
+        let mut keys: Vec<(u32, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (grid.index(&item.bbox().center()), i))
+            .collect();
+        keys.sort_unstable();
+        reorder(
+            &mut self.items,
+            &mut self.ids,
+            keys.into_iter().map(|(_, i)| i).collect(),
+        );
     }
 
     /// Builds the index and transfers the builders state into an HPRTree which is then returned, depends on the data being sorted (by [sort_items](#method.sort_items)) already
-    pub fn build_sorted(self) -> HPRTree<T> {
-        if self.items.len() < NODE_CAPACITY {
-            return HPRTree {
-                items: self.items,
-                extent: self.extent,
-                layer_start_index: Vec::new(),
-                node_bounds: Vec::new(),
-            };
+    pub fn build_sorted(self) -> HPRTree<T, C> {
+        if self.items.len() < self.node_capacity {
+            return self.finish(Vec::new(), Vec::new());
         }
 
-        let layer_start_index = self.compute_layer_start_indices();
+        let layer_start_index = compute_layer_start_indices(self.items.len(), self.node_capacity);
 
         let mut node_bounds = vec![BBox::default(); *layer_start_index.last().unwrap()];
 
         self.compute_leaf_nodes(&layer_start_index, &mut node_bounds);
         self.compute_layer_nodes(&layer_start_index, &mut node_bounds);
 
+        self.finish(layer_start_index, node_bounds)
+    }
+
+    fn finish(self, layer_start_index: Vec<usize>, node_bounds: Vec<BBox<C>>) -> HPRTree<T, C> {
         HPRTree {
+            positions: sort_positions_by_id(&self.ids),
+            id_count: self.ids.len(),
             items: self.items,
+            ids: self.ids,
+            tombstones: Tombstones::default(),
+            removed: 0,
+            stale: 0,
             extent: self.extent,
             layer_start_index,
             node_bounds,
+            node_capacity: self.node_capacity,
+            hilbert_level: self.hilbert_level,
         }
     }
 
@@ -169,53 +305,34 @@ where
     }
 
     /// Returns the extent of the tree
-    pub fn extent(&self) -> BBox {
+    pub fn extent(&self) -> BBox<C> {
         self.extent.clone()
     }
 
-    fn compute_layer_start_indices(&self) -> Vec<usize> {
-        let mut item_count = self.items.len();
-        let mut layer_start_index =
-            Vec::with_capacity((item_count as f32).log(NODE_CAPACITY as f32).trunc() as usize);
-        let mut index: usize = 0;
-
-        loop {
-            layer_start_index.push(index);
-
-            item_count /= NODE_CAPACITY;
-
-            if item_count * NODE_CAPACITY != item_count {
-                item_count += 1;
-            }
-            index += item_count;
-
-            if item_count <= 1 {
-                break;
-            }
-        }
-        layer_start_index
-    }
-
-    fn compute_leaf_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox]) {
-        for i in 0..layer_start_index[1] {
-            for j in 0..=NODE_CAPACITY {
-                let index = NODE_CAPACITY * i + j;
+    fn compute_leaf_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox<C>]) {
+        for (i, node) in node_bounds
+            .iter_mut()
+            .enumerate()
+            .take(layer_start_index[1])
+        {
+            for j in 0..self.node_capacity {
+                let index = self.node_capacity * i + j;
                 if index >= self.items.len() {
                     return;
                 }
-                node_bounds[i].expand_to_include_spatially_indexable(&self.items[index]);
+                node.expand_to_include(&self.items[index].bbox());
             }
         }
     }
-    fn compute_layer_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox]) {
+    fn compute_layer_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox<C>]) {
         for i in 1..(layer_start_index.len() - 1) {
             let layer_start = layer_start_index[i];
             let layer_size = get_layer_size(i, layer_start_index);
             let child_layer_start = layer_start_index[i - 1];
             let child_layer_end = layer_start;
             for j in 0..layer_size {
-                let child_start = child_layer_start + NODE_CAPACITY * j;
-                for k in 0..=NODE_CAPACITY {
+                let child_start = child_layer_start + self.node_capacity * j;
+                for k in 0..self.node_capacity {
                     let index = child_start + k;
                     if index >= child_layer_end {
                         break;
@@ -237,142 +354,1089 @@ where
     }
 }
 
-impl<T> HPRTree<T>
+impl<T, C> FromIterator<T> for HPRTreeBuilder<T, C>
 where
-    T: SpatiallyIndexable,
-    T: Clone,
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
 {
-    fn query_node_children(
-        &self,
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T, C, Ids> Extend<T> for HPRTreeBuilder<T, C, Ids>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.items.reserve(iter.size_hint().0);
+        if self.keep_ids {
+            self.ids.reserve(iter.size_hint().0);
+        }
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+impl<T, C> FromIterator<T> for HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Collects the elements into a builder and builds the tree with the default options
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        HPRTreeBuilder::from_iter(iter).build()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T, C, Ids> HPRTreeBuilder<T, C, Ids>
+where
+    T: SpatiallyIndexableExtent<C> + Send + Sync,
+    C: Coordinate + Send + Sync,
+{
+    /// Like [build](#method.build) but sorts the data and computes the node bounds on the rayon thread pool, the resulting tree is identical to the one built sequentially
+    pub fn par_build(mut self) -> HPRTree<T, C> {
+        if self.items.len() < self.node_capacity {
+            return self.build_sorted();
+        }
+
+        self.par_sort_items();
+
+        self.par_build_sorted()
+    }
+
+    /// Like [sort_items](#method.sort_items) but computes the hilbert indices and sorts on the rayon thread pool, the sort is unstable but the (key, position) pairs are unique so the order is the same as the sequential one
+    pub fn par_sort_items(&mut self) {
+        let grid = HilbertGrid::new(&self.extent, self.hilbert_level);
+
+        let mut keys: Vec<(u32, usize)> = self
+            .items
+            .par_iter()
+            .enumerate()
+            .map(|(i, item)| (grid.index(&item.bbox().center()), i))
+            .collect();
+        keys.par_sort_unstable();
+        reorder(
+            &mut self.items,
+            &mut self.ids,
+            keys.into_iter().map(|(_, i)| i).collect(),
+        );
+    }
+
+    /// Like [build_sorted](#method.build_sorted) but computes the node bounds of each layer on the rayon thread pool
+    pub fn par_build_sorted(self) -> HPRTree<T, C> {
+        if self.items.len() < self.node_capacity {
+            return self.build_sorted();
+        }
+
+        let layer_start_index = compute_layer_start_indices(self.items.len(), self.node_capacity);
+
+        let mut node_bounds = vec![BBox::default(); *layer_start_index.last().unwrap()];
+
+        self.par_compute_leaf_nodes(&layer_start_index, &mut node_bounds);
+        self.par_compute_layer_nodes(&layer_start_index, &mut node_bounds);
+
+        self.finish(layer_start_index, node_bounds)
+    }
+
+    fn par_compute_leaf_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox<C>]) {
+        node_bounds[..layer_start_index[1]]
+            .par_iter_mut()
+            .zip(self.items.par_chunks(self.node_capacity))
+            .for_each(|(node, items)| {
+                for item in items {
+                    node.expand_to_include(&item.bbox());
+                }
+            });
+    }
+
+    fn par_compute_layer_nodes(&self, layer_start_index: &[usize], node_bounds: &mut [BBox<C>]) {
+        // the layers depend on each other, only the nodes within a layer are computed in parallel
+        for i in 1..(layer_start_index.len() - 1) {
+            let (children, nodes) = node_bounds.split_at_mut(layer_start_index[i]);
+            nodes[..get_layer_size(i, layer_start_index)]
+                .par_iter_mut()
+                .zip(children[layer_start_index[i - 1]..].par_chunks(self.node_capacity))
+                .for_each(|(node, children)| {
+                    for child in children {
+                        node.expand_to_include(child);
+                    }
+                });
+        }
+    }
+}
+
+impl<T, C> HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn query_node_children<'a>(
+        &'a self,
         layer_index: usize,
         block_offset: &usize,
-        query_env: &BBox,
-        candidate_list: &mut Vec<T>,
-    ) {
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         let layer_start = self.layer_start_index[layer_index];
         let layer_end = self.layer_start_index[layer_index + 1];
-        for i in 0..NODE_CAPACITY {
+        for i in 0..self.node_capacity {
             let node_offset = block_offset + i;
             if node_offset + layer_start >= layer_end {
-                return;
+                return ControlFlow::Continue(());
             }
-            self.query_node(&layer_index, &node_offset, query_env, candidate_list)
+            self.query_node(&layer_index, &node_offset, query_shape, visitor)?;
         }
+        ControlFlow::Continue(())
     }
 
-    fn query_items(&self, block_start: usize, query_env: &BBox, candidate_list: &mut Vec<T>) {
-        for i in 0..NODE_CAPACITY {
+    fn query_items<'a>(
+        &'a self,
+        block_start: usize,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for i in 0..self.node_capacity {
             let item_index = block_start + i;
             if item_index >= self.items.len() {
-                return;
+                return ControlFlow::Continue(());
             }
             let current_item = &self.items[item_index];
-            if query_env.contains_spatially_indexable(current_item) {
-                candidate_list.push(current_item.clone());
+            if !self.tombstones.contains(item_index)
+                && query_shape.matches_item(&current_item.bbox())
+            {
+                visitor(current_item)?;
             }
         }
+        ControlFlow::Continue(())
     }
 
-    fn query_node(
-        &self,
+    fn query_node<'a>(
+        &'a self,
         layer_index: &usize,
         node_offset: &usize,
-        query_env: &BBox,
-        candidate_list: &mut Vec<T>,
-    ) {
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         let layer_start = self.layer_start_index[*layer_index];
         let node_index = layer_start + *node_offset;
 
-        if !query_env.intersects(&self.node_bounds[node_index]) {
-            return;
+        if !query_shape.intersects_bbox(&self.node_bounds[node_index]) {
+            return ControlFlow::Continue(());
         }
-        let child_node_offset = node_offset * NODE_CAPACITY;
-        if *layer_index != 0 {
-            self.query_node_children(
-                *layer_index - 1,
-                &child_node_offset,
-                query_env,
-                candidate_list,
+        if query_shape.contains_bbox(&self.node_bounds[node_index]) {
+            let item_range = get_subtree_item_range(
+                *layer_index,
+                *node_offset,
+                self.items.len(),
+                self.node_capacity,
             );
+            for item_index in item_range {
+                if !self.tombstones.contains(item_index) {
+                    visitor(&self.items[item_index])?;
+                }
+            }
+            return ControlFlow::Continue(());
+        }
+        let child_node_offset = node_offset * self.node_capacity;
+        if *layer_index != 0 {
+            self.query_node_children(*layer_index - 1, &child_node_offset, query_shape, visitor)
         } else {
-            self.query_items(child_node_offset, query_env, candidate_list);
+            self.query_items(child_node_offset, query_shape, visitor)
         }
     }
 
-    /// Queries the tree by bounding box returning a Vec of the found elements
-    pub fn query(&self, query_env: &BBox) -> Vec<T> {
-        if !self.extent.intersects(query_env) {
-            return Vec::new();
+    /// Queries the tree by bounding box returning a lazy iterator over references to the found elements
+    ///
+    /// Nothing is cloned and the tree is only traversed as far as the iterator is advanced, so stopping early (e.g. with `take` or `find`) skips the rest of the tree
+    pub fn query_iter<'a>(&'a self, query_env: &BBox<C>) -> impl Iterator<Item = &'a T> + 'a {
+        HPRTreeQueryIter::new(self, query_env)
+    }
+
+    /// Queries the tree by bounding box calling the visitor for each found element, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    ///
+    /// Returns [ControlFlow::Break] if the traversal was stopped by the visitor, which makes existence checks or searching for the first element matching a predicate cheap
+    pub fn query_visit<'a>(
+        &'a self,
+        query_env: &BBox<C>,
+        mut visitor: impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(query_env, &mut visitor)
+    }
+
+    /// Counts the elements within the bbox without cloning them
+    ///
+    /// Nodes that are fully contained in the bbox are counted as a whole without looking at each element
+    pub fn count(&self, query_env: &BBox<C>) -> usize {
+        self.count_shape(query_env)
+    }
+
+    /// Checks whether there is any element within the bbox, stopping at the first one found
+    pub fn any(&self, query_env: &BBox<C>) -> bool {
+        self.query_visit(query_env, |_| ControlFlow::Break(()))
+            .is_break()
+    }
+
+    fn count_shape(&self, query_shape: &impl QueryShape<C>) -> usize {
+        if !query_shape.intersects_bbox(&self.extent) {
+            return 0;
         }
 
-        let n_guessed_candidates =
-            self.avg_entries() * query_env.height() * query_env.width() * 1.5;
-        let mut candidate_list = Vec::with_capacity((n_guessed_candidates) as usize);
+        if self.layer_start_index.is_empty() {
+            return self.count_items(0..self.items.len(), query_shape);
+        }
 
-        self.query_with_list(query_env, &mut candidate_list);
+        let layer_index = self.layer_start_index.len() - 2;
+        let layer_size = get_layer_size(layer_index, &self.layer_start_index);
 
-        candidate_list
+        (0..layer_size)
+            .map(|i| self.count_node(layer_index, i, query_shape))
+            .sum()
     }
 
-    /// Queries the tree by bounding box and pushes the found elements onto the vector, useful if the usecase enables better estimates for how many elements will be found (to reduce the chance for reallocation or overallocation)
-    pub fn query_with_list(&self, query_env: &BBox, candidate_list: &mut Vec<T>) {
-        if !self.extent.intersects(query_env) {
-            return;
+    fn count_node(
+        &self,
+        layer_index: usize,
+        node_offset: usize,
+        query_shape: &impl QueryShape<C>,
+    ) -> usize {
+        let layer_start = self.layer_start_index[layer_index];
+        let node_bounds = &self.node_bounds[layer_start + node_offset];
+
+        if !query_shape.intersects_bbox(node_bounds) {
+            return 0;
+        }
+        if query_shape.contains_bbox(node_bounds) {
+            let item_range = get_subtree_item_range(
+                layer_index,
+                node_offset,
+                self.items.len(),
+                self.node_capacity,
+            );
+            return item_range.len() - self.tombstones.count_in(item_range);
+        }
+        if layer_index == 0 {
+            let item_range =
+                get_subtree_item_range(0, node_offset, self.items.len(), self.node_capacity);
+            return self.count_items(item_range, query_shape);
+        }
+
+        let child_layer_size = layer_start - self.layer_start_index[layer_index - 1];
+        let child_node_offset = node_offset * self.node_capacity;
+        (child_node_offset..(child_node_offset + self.node_capacity).min(child_layer_size))
+            .map(|i| self.count_node(layer_index - 1, i, query_shape))
+            .sum()
+    }
+
+    fn count_items(&self, item_range: Range<usize>, query_shape: &impl QueryShape<C>) -> usize {
+        item_range
+            .filter(|item_index| {
+                !self.tombstones.contains(*item_index)
+                    && query_shape.matches_item(&self.items[*item_index].bbox())
+            })
+            .count()
+    }
+
+    /// Like [query_visit](#method.query_visit), but the elements have to satisfy the given spatial predicate with regard to the query envelope
+    pub fn query_visit_with_predicate<'a>(
+        &'a self,
+        query_env: &BBox<C>,
+        predicate: SpatialPredicate,
+        mut visitor: impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(&PredicateQuery::new(query_env, predicate), &mut visitor)
+    }
+
+    /// Queries the tree by polygon calling the visitor for each element within it, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn query_visit_polygon<'a>(
+        &'a self,
+        polygon: &Polygon<C>,
+        mut visitor: impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(polygon, &mut visitor)
+    }
+
+    /// Queries the tree by the polygons of a multipolygon calling the visitor once for each element within any of them, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn query_visit_multi_polygon<'a>(
+        &'a self,
+        polygons: &[Polygon<C>],
+        mut visitor: impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(&MultiPolygonQuery(polygons), &mut visitor)
+    }
+
+    fn visit_shape<'a>(
+        &'a self,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(&'a T) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        if !query_shape.intersects_bbox(&self.extent) {
+            return ControlFlow::Continue(());
         }
 
         if self.layer_start_index.is_empty() {
-            self.query_items(0, query_env, candidate_list);
-            return;
+            return self.query_items(0, query_shape, visitor);
         }
 
         let layer_index = self.layer_start_index.len() - 2;
         let layer_size = get_layer_size(layer_index, &self.layer_start_index);
 
         for i in 0..layer_size {
-            self.query_node(&layer_index, &i, query_env, candidate_list);
+            self.query_node(&layer_index, &i, query_shape, visitor)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn push_nearest_node_children(
+        &self,
+        layer_index: usize,
+        block_offset: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        let layer_start = self.layer_start_index[layer_index];
+        let layer_end = self.layer_start_index[layer_index + 1];
+        for i in 0..self.node_capacity {
+            let node_offset = block_offset + i;
+            if node_offset + layer_start >= layer_end {
+                return;
+            }
+            self.push_nearest_node(layer_index, node_offset, point, metric, queue);
         }
     }
 
+    fn push_nearest_node(
+        &self,
+        layer_index: usize,
+        node_offset: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        let node_index = self.layer_start_index[layer_index] + node_offset;
+        queue.push(NearestCandidate {
+            distance: metric.min_distance_to_bbox(point, &self.node_bounds[node_index]),
+            kind: NearestCandidateKind::Node {
+                layer_index,
+                node_offset,
+            },
+        });
+    }
+
+    fn push_nearest_items(
+        &self,
+        block_start: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        for i in 0..self.node_capacity {
+            let item_index = block_start + i;
+            if item_index >= self.items.len() {
+                return;
+            }
+            if self.tombstones.contains(item_index) {
+                continue;
+            }
+            queue.push(NearestCandidate {
+                distance: distance_to_item(metric, point, &self.items[item_index].bbox()),
+                kind: NearestCandidateKind::Item(item_index),
+            });
+        }
+    }
+
+    fn nearest_k_indices(
+        &self,
+        point: &Point<C>,
+        k: usize,
+        metric: &impl DistanceMetric,
+    ) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.items.len()));
+        if k == 0 {
+            return result;
+        }
+
+        let mut queue = BinaryHeap::new();
+        if self.layer_start_index.is_empty() {
+            self.push_nearest_items(0, point, metric, &mut queue);
+        } else {
+            let layer_index = self.layer_start_index.len() - 2;
+            let layer_size = get_layer_size(layer_index, &self.layer_start_index);
+
+            for i in 0..layer_size {
+                self.push_nearest_node(layer_index, i, point, metric, &mut queue);
+            }
+        }
+
+        while let Some(candidate) = queue.pop() {
+            match candidate.kind {
+                NearestCandidateKind::Item(item_index) => {
+                    result.push(item_index);
+                    if result.len() == k {
+                        break;
+                    }
+                }
+                NearestCandidateKind::Node {
+                    layer_index,
+                    node_offset,
+                } => {
+                    let child_node_offset = node_offset * self.node_capacity;
+                    if layer_index != 0 {
+                        self.push_nearest_node_children(
+                            layer_index - 1,
+                            child_node_offset,
+                            point,
+                            metric,
+                            &mut queue,
+                        );
+                    } else {
+                        self.push_nearest_items(child_node_offset, point, metric, &mut queue);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
     /// Returns how many elements are in an area unit on average, may help with guessing how many entities will be found in a given bounding box if the entries are somewhat evenly distributed
     pub fn avg_entries(&self) -> f32 {
-        let area = self.extent.height() * self.extent.width();
-        if area == 0f32 {
-            return self.items.len() as f32;
+        let area = self.extent.area();
+        if area == 0f64 {
+            return self.len() as f32;
         }
-        self.items.len() as f32 / area
+        (self.len() as f64 / area) as f32
     }
 
     /// Returns how many bytes are taken up by the trees data
     pub fn current_size_in_bytes(&self) -> usize {
         self.items.len() * size_of::<T>()
+            + (self.ids.len() + self.positions.len()) * size_of::<usize>()
+            + self.tombstones.words.len() * size_of::<u64>()
             + self.layer_start_index.len() * size_of::<usize>()
-            + self.node_bounds.len() * size_of::<BBox>()
+            + self.node_bounds.len() * size_of::<BBox<C>>()
             + size_of::<Self>()
     }
 
-    /// Approximates how many bytes would be taken up by the data of a tree with a given size and index element type
+    /// Approximates how many bytes would be taken up by the data of a tree with a given size and index element type, built without ids
     pub fn projected_size_in_bytes(elems: usize) -> usize {
         elems * size_of::<T>()
-            + (elems as f32).log(NODE_CAPACITY as f32).trunc() as usize * size_of::<usize>()
-            + (elems as f64*0.0667+2.2143).trunc() as usize * size_of::<BBox>()
+            + (elems as f32).log(DEFAULT_NODE_CAPACITY as f32).trunc() as usize * size_of::<usize>()
+            + (elems as f64*0.0667+2.2143).trunc() as usize * size_of::<BBox<C>>()
             // approximate linear regression from the following values
             // 16200    64800   145800  259200  405000  583200  793800  1036800
             // 1082     4323    9722    17283   27002   38882   52921   69124
             + size_of::<Self>()
     }
 
-    /// Returns the number of elements in the tree
+    /// Returns the number of elements in the tree, removed elements are not counted
     pub fn len(&self) -> usize {
-        self.items.len()
+        self.items.len() - self.removed
     }
 
     /// Returns whether the tree is empty
     pub fn is_empty(&self) -> bool {
-        self.items.is_empty()
+        self.len() == 0
     }
 
     /// Returns the extent of the tree
-    pub fn extent(&self) -> BBox {
+    pub fn extent(&self) -> BBox<C> {
         self.extent.clone()
     }
+
+    /// Returns the number of children per node the tree was built with
+    pub fn node_capacity(&self) -> usize {
+        self.node_capacity
+    }
+
+    /// Returns the number of bits per axis the tree was sorted with along the hilbert curve
+    pub fn hilbert_level(&self) -> u32 {
+        self.hilbert_level
+    }
+
+    /// Returns the number of node layers, trees with fewer elements than the node capacity have none
+    pub fn depth(&self) -> usize {
+        self.layer_start_index.len().saturating_sub(1)
+    }
+
+    /// Returns the bounds of the nodes in a layer, layer 0 being the leaves right above the elements and [depth](#method.depth) - 1 the top layer
+    pub fn layer_bounds(&self, layer: usize) -> &[BBox<C>] {
+        &self.node_bounds[self.layer_start_index[layer]..self.layer_start_index[layer + 1]]
+    }
+
+    /// Takes the elements with their ids out of a tree built with ids in the order they are stored in, leaving out removed ones
+    pub(crate) fn into_entries(self) -> impl Iterator<Item = (usize, T)> {
+        let tombstones = self.tombstones;
+        self.ids
+            .into_iter()
+            .zip(self.items)
+            .enumerate()
+            .filter(move |(position, _)| !tombstones.contains(*position))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Iterates over the elements with their ids of a tree built with ids in the order they are stored in, leaving out removed ones
+    pub(crate) fn iter_entries(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.ids
+            .iter()
+            .copied()
+            .zip(&self.items)
+            .enumerate()
+            .filter(|(position, _)| !self.tombstones.contains(*position))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Iterates over the ids of the removed elements which are still marked in the tree
+    pub(crate) fn removed_ids(&self) -> impl Iterator<Item = ItemId> + '_ {
+        let marked = if self.removed == 0 { 0 } else { self.ids.len() };
+        (0..marked)
+            .filter(|position| self.tombstones.contains(*position))
+            .map(|position| ItemId(self.ids[position]))
+    }
+
+    /// Returns the k elements closest to a point in order of increasing distance without cloning them
+    pub(crate) fn nearest_k_items(
+        &self,
+        point: &Point<C>,
+        k: usize,
+        metric: &impl DistanceMetric,
+    ) -> impl Iterator<Item = &T> {
+        self.nearest_k_indices(point, k, metric)
+            .into_iter()
+            .map(|item_index| &self.items[item_index])
+    }
+
+    /// Removes the first element within the bbox which the predicate matches, for finding elements by their contents instead of their id
+    pub(crate) fn remove_matching(
+        &mut self,
+        query_env: &BBox<C>,
+        mut predicate: impl FnMut(&T) -> bool,
+    ) -> bool {
+        let mut found = HPRTreeQueryIter::new(self, query_env);
+        let Some(position) = std::iter::from_fn(|| found.next_position())
+            .find(|position| predicate(&self.items[*position]))
+        else {
+            return false;
+        };
+        self.tombstones.insert(position);
+        self.removed += 1;
+        self.mark_stale();
+        true
+    }
+
+    /// Returns the number of ids handed out for the tree, including those of removed elements
+    pub(crate) fn id_count(&self) -> usize {
+        self.id_count
+    }
+
+    /// Raises the number of ids handed out for the tree, so a tree built from elements which had ids before does not hand them out again
+    pub(crate) fn with_id_count(mut self, id_count: usize) -> Self {
+        self.id_count = self.id_count.max(id_count);
+        self
+    }
+
+    /// Gives the elements of a tree built without ids the ids of their positions, trees with ids are returned as they are
+    pub(crate) fn with_position_ids(mut self) -> Self {
+        if self.ids.len() != self.items.len() {
+            self.ids = (0..self.items.len()).collect();
+            self.positions = self.ids.clone();
+            self.id_count = self.id_count.max(self.items.len());
+        }
+        self
+    }
+
+    /// Borrows the parts of the tree a join traverses
+    pub(crate) fn join_side(&self) -> JoinSide<'_, T, C> {
+        JoinSide {
+            items: &self.items,
+            tombstones: &self.tombstones,
+            extent: &self.extent,
+            layer_start_index: &self.layer_start_index,
+            node_bounds: &self.node_bounds,
+            node_capacity: self.node_capacity,
+        }
+    }
+
+    fn position(&self, id: ItemId) -> Option<usize> {
+        find_position(&self.ids, &self.positions, id.0)
+            .filter(|position| !self.tombstones.contains(*position))
+    }
+
+    /// Returns the element with the given id, if it has not been removed
+    ///
+    /// Ids are looked up by binary search, trees built without [ids](struct.HPRTreeBuilder.html#method.with_ids) have no elements by id
+    pub fn get(&self, id: ItemId) -> Option<&T> {
+        self.position(id).map(|position| &self.items[position])
+    }
+
+    /// Removes the element with the given id, returning whether there was one
+    ///
+    /// The element is only marked as removed and skipped by queries until the tree is [compacted](#method.compact), which is left to the caller, see [needs_compaction](#method.needs_compaction)
+    pub fn remove(&mut self, id: ItemId) -> bool {
+        let Some(position) = self.position(id) else {
+            return false;
+        };
+        self.tombstones.insert(position);
+        self.removed += 1;
+        self.mark_stale();
+        true
+    }
+
+    /// Replaces the element with the given id (e.g. by a moved copy of it), returning the previous one or None if there is no element with that id
+    ///
+    /// The element keeps its place in the tree and the bounds of the nodes above it are expanded to include it, which leaves them less tight until the tree is compacted
+    pub fn update(&mut self, id: ItemId, item: T) -> Option<T> {
+        let position = self.position(id)?;
+        let bbox = item.bbox();
+        let previous = mem::replace(&mut self.items[position], item);
+        self.extent.expand_to_include(&bbox);
+        expand_ancestor_bounds(
+            &self.layer_start_index,
+            &mut self.node_bounds,
+            self.node_capacity,
+            position,
+            &bbox,
+        );
+        self.mark_stale();
+        Some(previous)
+    }
+
+    /// Moves the element with the given id to a new point, returning whether there was such an element
+    ///
+    /// Like [update](#method.update) the element keeps its place in the tree and the bounds of the nodes above it are expanded to include the new point, which leaves them less tight until the tree is compacted
+    pub fn update_position(&mut self, id: ItemId, geom: Point<C>) -> bool
+    where
+        T: SpatiallyMovable<C>,
+    {
+        let Some(position) = self.position(id) else {
+            return false;
+        };
+        let bbox = geom.bbox();
+        self.extent.expand_to_include(&bbox);
+        expand_ancestor_bounds(
+            &self.layer_start_index,
+            &mut self.node_bounds,
+            self.node_capacity,
+            position,
+            &bbox,
+        );
+        self.items[position].set_position(&geom);
+        self.mark_stale();
+        true
+    }
+
+    fn mark_stale(&mut self) {
+        self.stale += 1;
+    }
+
+    /// Returns whether the share of removed and updated elements passed [COMPACTION_THRESHOLD], from where on queries visit noticeably more elements and nodes than they would in a [compacted](#method.compact) tree
+    pub fn needs_compaction(&self) -> bool {
+        self.stale as f64 > self.items.len() as f64 * COMPACTION_THRESHOLD
+    }
+
+    /// Rebuilds the tree without the removed elements and with tight bounds around the updated ones, the remaining elements keep their ids
+    ///
+    /// This sorts and builds the tree again in O(n log n), trees are never compacted implicitly
+    pub fn compact(&mut self) {
+        let id_count = self.id_count;
+        let keep_ids = !self.ids.is_empty();
+        let mut builder = HPRTreeBuilder::<T, C, WithoutIds> {
+            items: Vec::with_capacity(self.len()),
+            ids: Vec::with_capacity(if keep_ids { self.len() } else { 0 }),
+            keep_ids,
+            extent: BBox::default(),
+            node_capacity: self.node_capacity,
+            hilbert_level: self.hilbert_level,
+            id_mode: PhantomData,
+        };
+        let items = mem::take(&mut self.items);
+        let ids = mem::take(&mut self.ids);
+        for (position, item) in items.into_iter().enumerate() {
+            if !self.tombstones.contains(position) {
+                builder.extent.expand_to_include(&item.bbox());
+                builder.items.push(item);
+                if keep_ids {
+                    builder.ids.push(ids[position]);
+                }
+            }
+        }
+        *self = builder.build();
+        // the ids of removed elements are not handed out again
+        self.id_count = id_count;
+    }
+}
+
+impl<T, C> HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+    T: Clone,
+{
+    /// Builds a new tree from clones of the remaining elements and the given new ones with their ids leaving out the (sorted) removed ids, this tree is left untouched
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn rebuild_with(&self, items: Vec<T>, ids: Vec<usize>, removed: &[usize]) -> Self {
+        // ids handed out for removed new elements count as well, so they are not handed out again by a tree restored from this one
+        let id_count = ids
+            .iter()
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0)
+            .max(self.id_count);
+        let mut builder = HPRTreeBuilder::<T, C, WithIds> {
+            items: Vec::with_capacity(self.len() + items.len()),
+            ids: Vec::with_capacity(self.len() + ids.len()),
+            keep_ids: true,
+            extent: BBox::default(),
+            node_capacity: self.node_capacity,
+            hilbert_level: self.hilbert_level,
+            id_mode: PhantomData,
+        };
+        let is_removed = |id: &usize| removed.binary_search(id).is_ok();
+        for (position, (item, id)) in self.items.iter().zip(&self.ids).enumerate() {
+            if !self.tombstones.contains(position) && !is_removed(id) {
+                builder.extent.expand_to_include(&item.bbox());
+                builder.items.push(item.clone());
+                builder.ids.push(*id);
+            }
+        }
+        for (item, id) in items.into_iter().zip(ids) {
+            if is_removed(&id) {
+                continue;
+            }
+            builder.extent.expand_to_include(&item.bbox());
+            builder.items.push(item);
+            builder.ids.push(id);
+        }
+        let mut tree = builder.build();
+        tree.id_count = id_count;
+        tree
+    }
+
+    /// Queries the tree by bounding box returning a Vec of the found elements
+    pub fn query(&self, query_env: &BBox<C>) -> Vec<T> {
+        if !self.extent.intersects(query_env) {
+            return Vec::new();
+        }
+
+        let n_guessed_candidates = self.avg_entries() as f64 * query_env.area() * 1.5;
+        let mut candidate_list =
+            Vec::with_capacity((n_guessed_candidates as usize).min(self.items.len()));
+
+        self.query_with_list(query_env, &mut candidate_list);
+
+        candidate_list
+    }
+
+    /// Queries the tree by bounding box and pushes the found elements onto the vector, useful if the usecase enables better estimates for how many elements will be found (to reduce the chance for reallocation or overallocation)
+    pub fn query_with_list(&self, query_env: &BBox<C>, candidate_list: &mut Vec<T>) {
+        self.query_shape_with_list(query_env, candidate_list);
+    }
+
+    /// Queries the tree for all elements within a given (euclidean) distance of a point, returning a Vec of the found elements
+    ///
+    /// Nodes are pruned by their distance to the center, so unlike querying with the circles bounding box no elements outside of the circle are looked at (or cloned) needlessly
+    pub fn query_within_distance(&self, center: &Point<C>, radius: f64) -> Vec<T> {
+        let mut candidate_list = Vec::new();
+        self.query_within_distance_with_list(center, radius, &mut candidate_list);
+        candidate_list
+    }
+
+    /// Queries the tree for all elements within a given (euclidean) distance of a point and pushes the found elements onto the vector
+    pub fn query_within_distance_with_list(
+        &self,
+        center: &Point<C>,
+        radius: f64,
+        candidate_list: &mut Vec<T>,
+    ) {
+        self.query_shape_with_list(
+            &DistanceQuery::new(center, radius, &Euclidean),
+            candidate_list,
+        );
+    }
+
+    /// Queries the tree for all elements within a given distance of a point as measured by the given metric, returning a Vec of the found elements
+    ///
+    /// For example all elements within 5km of a lat/lon coordinate can be found with `query_within_distance_with_metric(&center, 5000f64, &Haversine::default())`
+    pub fn query_within_distance_with_metric(
+        &self,
+        center: &Point<C>,
+        radius: f64,
+        metric: &impl DistanceMetric,
+    ) -> Vec<T> {
+        let mut candidate_list = Vec::new();
+        self.query_shape_with_list(
+            &DistanceQuery::new(center, radius, metric),
+            &mut candidate_list,
+        );
+        candidate_list
+    }
+
+    /// Queries the tree for all elements whose bbox satisfies the given spatial predicate with regard to the query envelope, returning a Vec of the found elements
+    ///
+    /// [SpatialPredicate::Intersects] gives the same result as [query](#method.query), which for elements without an extent is the same as them being within the envelope
+    pub fn query_with_predicate(&self, query_env: &BBox<C>, predicate: SpatialPredicate) -> Vec<T> {
+        let mut candidate_list = Vec::new();
+        self.query_shape_with_list(
+            &PredicateQuery::new(query_env, predicate),
+            &mut candidate_list,
+        );
+        candidate_list
+    }
+
+    /// Queries the tree for all elements within the polygon given by its exterior ring, returning a Vec of the found elements
+    pub fn query_polygon(&self, exterior: &[Point<C>]) -> Vec<T> {
+        self.query_polygon_with_holes(&Polygon::new(exterior.to_vec()))
+    }
+
+    /// Queries the tree for all elements within the polygon, returning a Vec of the found elements
+    ///
+    /// Nodes are classified as disjoint from, intersecting or fully inside the polygon, the elements of nodes fully inside are taken without testing each of them
+    pub fn query_polygon_with_holes(&self, polygon: &Polygon<C>) -> Vec<T> {
+        let mut candidate_list = Vec::new();
+        self.query_shape_with_list(polygon, &mut candidate_list);
+        candidate_list
+    }
+
+    /// Queries the tree for all elements within any of the polygons of a multipolygon (e.g. a [Geometry::MultiPolygon](enum.Geometry.html#variant.MultiPolygon)), returning a Vec of the found elements
+    ///
+    /// The polygons are tested together in a single traversal, so an element within several overlapping polygons is found only once
+    pub fn query_multi_polygon(&self, polygons: &[Polygon<C>]) -> Vec<T> {
+        let mut candidate_list = Vec::new();
+        self.query_shape_with_list(&MultiPolygonQuery(polygons), &mut candidate_list);
+        candidate_list
+    }
+
+    fn query_shape_with_list(&self, query_shape: &impl QueryShape<C>, candidate_list: &mut Vec<T>) {
+        let _ = self.visit_shape(query_shape, &mut |item| {
+            candidate_list.push(item.clone());
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// Finds the k elements closest to a point (by euclidean distance), returned in order of increasing distance
+    ///
+    /// The nodes are visited best-first, so only the parts of the tree that may contain one of the k closest elements are looked at
+    pub fn nearest_k(&self, point: &Point<C>, k: usize) -> Vec<T> {
+        self.nearest_k_with_metric(point, k, &Euclidean)
+    }
+
+    /// Finds the k elements closest to a point as measured by the given metric, returned in order of increasing distance
+    pub fn nearest_k_with_metric(
+        &self,
+        point: &Point<C>,
+        k: usize,
+        metric: &impl DistanceMetric,
+    ) -> Vec<T> {
+        self.nearest_k_indices(point, k, metric)
+            .into_iter()
+            .map(|item_index| self.items[item_index].clone())
+            .collect()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T, C> TryFrom<TreeData<T, C>> for HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    type Error = InvalidTreeError;
+
+    fn try_from(data: TreeData<T, C>) -> Result<Self, Self::Error> {
+        validate_tree(
+            &data.items,
+            |item| item.bbox(),
+            &data.extent,
+            &data.layer_start_index,
+            &data.node_bounds,
+            data.node_capacity,
+        )?;
+        let (ids, positions, removed, id_count) = validate_ids(
+            data.ids,
+            &data.tombstones,
+            data.items.len(),
+            data.id_count,
+            data.hilbert_level,
+        )?;
+        Ok(Self {
+            items: data.items,
+            ids,
+            positions,
+            id_count,
+            tombstones: data.tombstones,
+            removed,
+            stale: data.stale.max(removed),
+            extent: data.extent,
+            layer_start_index: data.layer_start_index,
+            node_bounds: data.node_bounds,
+            node_capacity: data.node_capacity,
+            hilbert_level: data.hilbert_level,
+        })
+    }
+}
+
+impl<T, C> HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C> + MappableItem,
+    C: MappableCoordinate,
+{
+    /// Writes the tree in the mapped format, which can be queried without deserialising it by opening the written bytes (e.g. a memory mapped file) as a [MappedHPRTree](struct.MappedHPRTree.html)
+    ///
+    /// Fails if elements have been removed since the tree was last compacted
+    pub fn write_mapped(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.check_no_removed()?;
+        write_mapped(
+            writer,
+            &self.items,
+            |item| (item.bbox(), item),
+            &self.extent,
+            &self.layer_start_index,
+            &self.node_bounds,
+            self.node_capacity,
+        )
+    }
+}
+
+impl<T, C> HPRTree<T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    /// Writes the tree as the index section of a FlatGeobuf file, the features have to be written in the order of the tree (the order feature_offset is called in) with feature_offset returning the byte offset of each within the feature section
+    ///
+    /// Fails if the node capacity is larger than the 65535 FlatGeobuf supports or if elements have been removed since the tree was last compacted
+    pub fn write_flatgeobuf_index(
+        &self,
+        writer: &mut impl Write,
+        mut feature_offset: impl FnMut(&T) -> u64,
+    ) -> io::Result<()> {
+        self.check_no_removed()?;
+        write_flatgeobuf_index(
+            writer,
+            &self.items,
+            |item| (item.bbox(), feature_offset(item)),
+            &self.extent,
+            &self.layer_start_index,
+            &self.node_bounds,
+            self.node_capacity,
+        )
+    }
+
+    /// The written formats have no notion of removed elements, so only trees without any can be written
+    fn check_no_removed(&self) -> io::Result<()> {
+        if self.removed > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the tree contains removed elements, compact it before writing it",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The iterator behind [query_iter](struct.HPRTree.html#method.query_iter), traverses the tree depth-first using an explicit stack of nodes still to be visited
+struct HPRTreeQueryIter<'a, T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    tree: &'a HPRTree<T, C>,
+    query_env: BBox<C>,
+    node_stack: Vec<(usize, usize)>,
+    item_index: usize,
+    item_end: usize,
+}
+
+impl<'a, T, C> HPRTreeQueryIter<'a, T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn new(tree: &'a HPRTree<T, C>, query_env: &BBox<C>) -> Self {
+        let mut iter = HPRTreeQueryIter {
+            tree,
+            query_env: query_env.clone(),
+            node_stack: Vec::new(),
+            item_index: 0,
+            item_end: 0,
+        };
+
+        if !tree.extent.intersects(query_env) {
+            return iter;
+        }
+
+        if tree.layer_start_index.is_empty() {
+            iter.item_end = tree.items.len();
+            return iter;
+        }
+
+        let layer_index = tree.layer_start_index.len() - 2;
+        let layer_size = get_layer_size(layer_index, &tree.layer_start_index);
+        // pushed in reverse so the nodes are popped in the same order query visits them
+        for i in (0..layer_size).rev() {
+            iter.node_stack.push((layer_index, i));
+        }
+        iter
+    }
+
+    /// Advances to the position of the next found element
+    fn next_position(&mut self) -> Option<usize> {
+        loop {
+            while self.item_index < self.item_end {
+                let position = self.item_index;
+                self.item_index += 1;
+                if !self.tree.tombstones.contains(position)
+                    && self.query_env.intersects(&self.tree.items[position].bbox())
+                {
+                    return Some(position);
+                }
+            }
+
+            let (layer_index, node_offset) = self.node_stack.pop()?;
+            self.visit_node(layer_index, node_offset);
+        }
+    }
+
+    fn visit_node(&mut self, layer_index: usize, node_offset: usize) {
+        let layer_start = self.tree.layer_start_index[layer_index];
+        if !self
+            .query_env
+            .intersects(&self.tree.node_bounds[layer_start + node_offset])
+        {
+            return;
+        }
+
+        let child_node_offset = node_offset * self.tree.node_capacity;
+        if layer_index != 0 {
+            let child_layer_start = self.tree.layer_start_index[layer_index - 1];
+            let child_layer_size = layer_start - child_layer_start;
+            let child_node_end =
+                (child_node_offset + self.tree.node_capacity).min(child_layer_size);
+            for i in (child_node_offset..child_node_end).rev() {
+                self.node_stack.push((layer_index - 1, i));
+            }
+        } else {
+            self.item_index = child_node_offset;
+            self.item_end =
+                (child_node_offset + self.tree.node_capacity).min(self.tree.items.len());
+        }
+    }
+}
+
+impl<'a, T, C> Iterator for HPRTreeQueryIter<'a, T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.next_position().map(|position| &tree.items[position])
+    }
 }
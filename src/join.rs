@@ -0,0 +1,240 @@
+use std::ops::ControlFlow;
+
+use crate::{get_layer_size, BBox, Coordinate, HPRTree, SpatiallyIndexableExtent, Tombstones};
+
+/// The parts of a tree a join traverses, borrowed from [HPRTree::join_side]
+pub(crate) struct JoinSide<'a, T, C>
+where
+    C: Coordinate,
+{
+    pub items: &'a [T],
+    pub tombstones: &'a Tombstones,
+    pub extent: &'a BBox<C>,
+    pub layer_start_index: &'a [usize],
+    pub node_bounds: &'a [BBox<C>],
+    pub node_capacity: usize,
+}
+
+/// A place in one of the joined trees, trees with fewer elements than the node capacity have no nodes so all of their elements are the children of the extent
+#[derive(Clone, Copy)]
+enum JoinEntry {
+    Extent,
+    Node {
+        layer_index: usize,
+        node_offset: usize,
+    },
+    Item(usize),
+}
+
+impl<'a, T, C> JoinSide<'a, T, C>
+where
+    T: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    fn root(&self) -> JoinEntry {
+        if self.layer_start_index.is_empty() {
+            JoinEntry::Extent
+        } else {
+            // the top layer always consists of a single node
+            JoinEntry::Node {
+                layer_index: self.layer_start_index.len() - 2,
+                node_offset: 0,
+            }
+        }
+    }
+
+    fn bounds(&self, entry: JoinEntry) -> BBox<C> {
+        match entry {
+            JoinEntry::Extent => self.extent.clone(),
+            JoinEntry::Node {
+                layer_index,
+                node_offset,
+            } => self.node_bounds[self.layer_start_index[layer_index] + node_offset].clone(),
+            JoinEntry::Item(item_index) => self.items[item_index].bbox(),
+        }
+    }
+
+    fn children(&self, entry: JoinEntry) -> impl Iterator<Item = JoinEntry> + '_ {
+        let (layer_index, range) = match entry {
+            JoinEntry::Extent => (None, 0..self.items.len()),
+            JoinEntry::Node {
+                layer_index,
+                node_offset,
+            } => {
+                let start = node_offset * self.node_capacity;
+                if layer_index == 0 {
+                    (
+                        None,
+                        start..(start + self.node_capacity).min(self.items.len()),
+                    )
+                } else {
+                    let end = (start + self.node_capacity)
+                        .min(get_layer_size(layer_index - 1, self.layer_start_index));
+                    (Some(layer_index - 1), start..end)
+                }
+            }
+            JoinEntry::Item(_) => (None, 0..0),
+        };
+        range.filter_map(move |i| match layer_index {
+            Some(layer_index) => Some(JoinEntry::Node {
+                layer_index,
+                node_offset: i,
+            }),
+            None if self.tombstones.contains(i) => None,
+            None => Some(JoinEntry::Item(i)),
+        })
+    }
+}
+
+/// Traverses both trees at once, only descending into pairs of nodes whose bounds satisfy the filter
+fn join_entries<'a, A, B, C>(
+    left: &JoinSide<'a, A, C>,
+    left_entry: JoinEntry,
+    right: &JoinSide<'a, B, C>,
+    right_entry: JoinEntry,
+    filter: &impl Fn(&BBox<C>, &BBox<C>) -> bool,
+    visitor: &mut impl FnMut(&'a A, &'a B) -> ControlFlow<()>,
+) -> ControlFlow<()>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    match (left_entry, right_entry) {
+        (JoinEntry::Item(left_index), JoinEntry::Item(right_index)) => {
+            visitor(&left.items[left_index], &right.items[right_index])
+        }
+        (JoinEntry::Item(_), _) => {
+            let left_bounds = left.bounds(left_entry);
+            for right_child in right.children(right_entry) {
+                if filter(&left_bounds, &right.bounds(right_child)) {
+                    join_entries(left, left_entry, right, right_child, filter, visitor)?;
+                }
+            }
+            ControlFlow::Continue(())
+        }
+        (_, JoinEntry::Item(_)) => {
+            let right_bounds = right.bounds(right_entry);
+            for left_child in left.children(left_entry) {
+                if filter(&left.bounds(left_child), &right_bounds) {
+                    join_entries(left, left_child, right, right_entry, filter, visitor)?;
+                }
+            }
+            ControlFlow::Continue(())
+        }
+        _ => {
+            let right_children: Vec<(JoinEntry, BBox<C>)> = right
+                .children(right_entry)
+                .map(|right_child| (right_child, right.bounds(right_child)))
+                .collect();
+            for left_child in left.children(left_entry) {
+                let left_bounds = left.bounds(left_child);
+                for (right_child, right_bounds) in &right_children {
+                    if filter(&left_bounds, right_bounds) {
+                        join_entries(left, left_child, right, *right_child, filter, visitor)?;
+                    }
+                }
+            }
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+fn join_trees<'a, A, B, C>(
+    left: &'a HPRTree<A, C>,
+    right: &'a HPRTree<B, C>,
+    filter: impl Fn(&BBox<C>, &BBox<C>) -> bool,
+    visitor: &mut impl FnMut(&'a A, &'a B) -> ControlFlow<()>,
+) -> ControlFlow<()>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    let left = left.join_side();
+    let right = right.join_side();
+    let (left_root, right_root) = (left.root(), right.root());
+    if !filter(&left.bounds(left_root), &right.bounds(right_root)) {
+        return ControlFlow::Continue(());
+    }
+    join_entries(&left, left_root, &right, right_root, &filter, visitor)
+}
+
+/// Finds all pairs of elements from the two trees whose bboxes intersect, calling the visitor for each pair found, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+///
+/// Both trees are traversed at once, so only pairs of nodes whose bounds intersect are looked into instead of querying one tree for every element of the other
+pub fn join_intersects_visit<'a, A, B, C>(
+    left: &'a HPRTree<A, C>,
+    right: &'a HPRTree<B, C>,
+    mut visitor: impl FnMut(&'a A, &'a B) -> ControlFlow<()>,
+) -> ControlFlow<()>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    join_trees(left, right, |a, b| a.intersects(b), &mut visitor)
+}
+
+/// Finds all pairs of elements from the two trees whose bboxes intersect
+pub fn join_intersects<'a, A, B, C>(
+    left: &'a HPRTree<A, C>,
+    right: &'a HPRTree<B, C>,
+) -> Vec<(&'a A, &'a B)>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    let mut result = Vec::new();
+    let _ = join_intersects_visit(left, right, |a, b| {
+        result.push((a, b));
+        ControlFlow::Continue(())
+    });
+    result
+}
+
+/// Finds all pairs of elements from the two trees within the given (euclidean) distance of each other, calling the visitor for each pair found, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+///
+/// The distance is measured between the bboxes of the elements, which for points is the distance between them. Pairs of nodes whose bounds are farther apart than the distance are skipped as a whole
+pub fn join_within_distance_visit<'a, A, B, C>(
+    left: &'a HPRTree<A, C>,
+    right: &'a HPRTree<B, C>,
+    distance: f64,
+    mut visitor: impl FnMut(&'a A, &'a B) -> ControlFlow<()>,
+) -> ControlFlow<()>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    if distance < 0f64 {
+        return ControlFlow::Continue(());
+    }
+    let distance_squared = distance * distance;
+    join_trees(
+        left,
+        right,
+        |a, b| a.min_distance_squared_to_bbox(b) <= distance_squared,
+        &mut visitor,
+    )
+}
+
+/// Finds all pairs of elements from the two trees within the given (euclidean) distance of each other
+pub fn join_within_distance<'a, A, B, C>(
+    left: &'a HPRTree<A, C>,
+    right: &'a HPRTree<B, C>,
+    distance: f64,
+) -> Vec<(&'a A, &'a B)>
+where
+    A: SpatiallyIndexableExtent<C>,
+    B: SpatiallyIndexableExtent<C>,
+    C: Coordinate,
+{
+    let mut result = Vec::new();
+    let _ = join_within_distance_visit(left, right, distance, |a, b| {
+        result.push((a, b));
+        ControlFlow::Continue(())
+    });
+    result
+}
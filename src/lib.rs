@@ -1,40 +1,79 @@
+use std::{cmp::Ordering, ops::Range};
+
+#[cfg(feature = "arc-swap")]
+mod concurrent;
+#[cfg(feature = "arc-swap")]
+pub use crate::concurrent::*;
+mod coordinate;
+pub use crate::coordinate::*;
+mod dynamic;
+pub use crate::dynamic::*;
+mod flatgeobuf;
+pub use crate::flatgeobuf::*;
+#[cfg(feature = "geojson")]
+mod geojson;
+#[cfg(feature = "geojson")]
+pub use crate::geojson::*;
+mod geometry;
+pub use crate::geometry::*;
 mod hprtree;
 pub use crate::hprtree::*;
 mod hprtree_wrapping;
 pub use crate::hprtree_wrapping::*;
+mod join;
+pub use crate::join::*;
+mod mapped;
+pub use crate::mapped::*;
+mod metric;
+pub use crate::metric::*;
+mod polygon;
+pub use crate::polygon::*;
+#[cfg(feature = "serde")]
+mod validation;
+#[cfg(feature = "serde")]
+pub use crate::validation::*;
+mod wkb;
+pub use crate::wkb::*;
+mod wkt;
+pub use crate::wkt::*;
 
 /// A simple stuct representing a bounding box / envelope, intended for lat/lon coordinates with lat=y, lon=x
 ///
 /// Used for querying the index and for the internal data structure of the HPRTree
 #[derive(Clone, Debug)]
-pub struct BBox {
-    pub minx: CoordinateType,
-    pub miny: CoordinateType,
-    pub maxx: CoordinateType,
-    pub maxy: CoordinateType,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BBox<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    pub minx: C,
+    pub miny: C,
+    pub maxx: C,
+    pub maxy: C,
 }
 
-impl Default for BBox {
-    /// The default of the bbox is min = f32::MAX and max = f32::MIN
+impl<C> Default for BBox<C>
+where
+    C: Coordinate,
+{
+    /// The default of the bbox is min = C::MAX and max = C::MIN
     ///
     /// This is so that "expanding to include"ing such a bbox results in whatever was used to expand the bbox by
     fn default() -> Self {
         Self {
-            minx: CoordinateType::MAX,
-            miny: CoordinateType::MAX,
-            maxx: CoordinateType::MIN,
-            maxy: CoordinateType::MIN,
+            minx: C::MAX,
+            miny: C::MAX,
+            maxx: C::MIN,
+            maxy: C::MIN,
         }
     }
 }
 
-impl BBox {
-    pub fn new(
-        minx: CoordinateType,
-        miny: CoordinateType,
-        maxx: CoordinateType,
-        maxy: CoordinateType,
-    ) -> Self {
+impl<C> BBox<C>
+where
+    C: Coordinate,
+{
+    pub fn new(minx: C, miny: C, maxx: C, maxy: C) -> Self {
         Self {
             minx,
             miny,
@@ -44,13 +83,18 @@ impl BBox {
     }
 
     /// Returns the width of the bbox
-    pub fn width(&self) -> CoordinateType {
-        self.maxx - self.minx
+    pub fn width(&self) -> C {
+        self.maxx.saturating_sub(self.minx)
     }
 
     /// Returns the height of the bbox
-    pub fn height(&self) -> CoordinateType {
-        self.maxy - self.miny
+    pub fn height(&self) -> C {
+        self.maxy.saturating_sub(self.miny)
+    }
+
+    /// Returns the area of the bbox
+    pub fn area(&self) -> f64 {
+        self.width().to_f64() * self.height().to_f64()
     }
 
     /// Expands the bbox to include another bbox
@@ -62,7 +106,7 @@ impl BBox {
     }
 
     /// Expands the bbox to include a point
-    pub fn expand_to_include_point(&mut self, point: &Point) {
+    pub fn expand_to_include_point(&mut self, point: &Point<C>) {
         self.minx = self.minx.min(point.x);
         self.miny = self.miny.min(point.y);
         self.maxx = self.maxx.max(point.x);
@@ -70,7 +114,7 @@ impl BBox {
     }
 
     /// Expands the bbox to include a point
-    pub fn expand_to_include_spatially_indexable(&mut self, point: &impl SpatiallyIndexable) {
+    pub fn expand_to_include_spatially_indexable(&mut self, point: &impl SpatiallyIndexable<C>) {
         self.minx = self.minx.min(point.x());
         self.miny = self.miny.min(point.y());
         self.maxx = self.maxx.max(point.x());
@@ -78,18 +122,41 @@ impl BBox {
     }
 
     /// Checks if a given point is contained within the bounds of the bbox
-    pub fn contains(&self, other: &Point) -> bool {
+    pub fn contains(&self, other: &Point<C>) -> bool {
         !(other.x > self.maxx || other.x < self.minx || other.y > self.maxy || other.y < self.miny)
     }
 
     /// Checks if a given point is contained within the bounds of the bbox
-    pub fn contains_spatially_indexable(&self, other: &impl SpatiallyIndexable) -> bool {
+    pub fn contains_spatially_indexable(&self, other: &impl SpatiallyIndexable<C>) -> bool {
         !(other.x() > self.maxx
             || other.x() < self.minx
             || other.y() > self.maxy
             || other.y() < self.miny)
     }
 
+    /// Returns the center of the bbox (rounded for integer coordinates)
+    pub fn center(&self) -> Point<C> {
+        Point {
+            x: C::from_f64(self.minx.to_f64() + self.width().to_f64() / 2f64),
+            y: C::from_f64(self.miny.to_f64() + self.height().to_f64() / 2f64),
+        }
+    }
+
+    fn min_point(&self) -> Point<C> {
+        Point {
+            x: self.minx,
+            y: self.miny,
+        }
+    }
+
+    /// Checks if a given bbox is fully contained within the bounds of the bbox
+    pub fn contains_bbox(&self, other: &Self) -> bool {
+        !(other.minx < self.minx
+            || other.maxx > self.maxx
+            || other.miny < self.miny
+            || other.maxy > self.maxy)
+    }
+
     /// Checks if a given bbox intersects the self bbox
     pub fn intersects(&self, other: &Self) -> bool {
         !(other.minx > self.maxx
@@ -97,34 +164,207 @@ impl BBox {
             || other.miny > self.maxy
             || other.maxy < self.miny)
     }
+
+    /// Returns the squared euclidean distance from a point to the closest point of the bbox (0 if the point is contained)
+    pub(crate) fn min_distance_squared(&self, point: &Point<C>) -> f64 {
+        let dx = axis_distance(point.x, self.minx, self.maxx);
+        let dy = axis_distance(point.y, self.miny, self.maxy);
+        dx * dx + dy * dy
+    }
+
+    /// Returns the squared euclidean distance between the closest points of two bboxes (0 if they intersect)
+    pub(crate) fn min_distance_squared_to_bbox(&self, other: &Self) -> f64 {
+        let dx = axis_gap(self.minx, self.maxx, other.minx, other.maxx);
+        let dy = axis_gap(self.miny, self.maxy, other.miny, other.maxy);
+        dx * dx + dy * dy
+    }
+}
+
+fn axis_distance<C>(value: C, min: C, max: C) -> f64
+where
+    C: Coordinate,
+{
+    if value < min {
+        min.to_f64() - value.to_f64()
+    } else if value > max {
+        value.to_f64() - max.to_f64()
+    } else {
+        0f64
+    }
+}
+
+fn axis_gap<C>(min_a: C, max_a: C, min_b: C, max_b: C) -> f64
+where
+    C: Coordinate,
+{
+    if max_a < min_b {
+        min_b.to_f64() - max_a.to_f64()
+    } else if max_b < min_a {
+        min_a.to_f64() - max_b.to_f64()
+    } else {
+        0f64
+    }
 }
 
 /// A simple point struct, intended for lat/lon coordinates with lat=y, lon=x
 #[derive(Clone, Debug)]
-pub struct Point {
-    pub x: CoordinateType,
-    pub y: CoordinateType,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    pub x: C,
+    pub y: C,
 }
 
-impl SpatiallyIndexable for Point {
-    fn x(&self) -> CoordinateType {
+impl<C> Point<C>
+where
+    C: Coordinate,
+{
+    /// Returns the squared euclidean distance between the point and a spatially indexable element
+    pub(crate) fn distance_squared(&self, other: &impl SpatiallyIndexable<C>) -> f64 {
+        let dx = self.x.to_f64() - other.x().to_f64();
+        let dy = self.y.to_f64() - other.y().to_f64();
+        dx * dx + dy * dy
+    }
+}
+
+impl<C> SpatiallyIndexable<C> for Point<C>
+where
+    C: Coordinate,
+{
+    fn x(&self) -> C {
         self.x
     }
 
-    fn y(&self) -> CoordinateType {
+    fn y(&self) -> C {
         self.y
     }
 }
 
 /// Trait that enables a struct to be spatially indexed
-pub trait SpatiallyIndexable {
-    fn x(&self) -> CoordinateType;
-    fn y(&self) -> CoordinateType;
+pub trait SpatiallyIndexable<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    fn x(&self) -> C;
+    fn y(&self) -> C;
+}
+
+/// Trait for indexed structs that can be moved to another point, which is what [update_position](struct.HPRTree.html#method.update_position) needs
+pub trait SpatiallyMovable<C = CoordinateType>: SpatiallyIndexable<C>
+where
+    C: Coordinate,
+{
+    fn set_position(&mut self, point: &Point<C>);
+}
+
+impl<C> SpatiallyMovable<C> for Point<C>
+where
+    C: Coordinate,
+{
+    fn set_position(&mut self, point: &Point<C>) {
+        self.x = point.x;
+        self.y = point.y;
+    }
 }
 
-const NODE_CAPACITY: usize = 16;
-const HILBERT_LEVEL: usize = 12;
-const H: usize = (1 << HILBERT_LEVEL) - 1;
+/// Trait that enables a struct with an extent (e.g. a building footprint or a road segment) to be spatially indexed by its bounding box
+///
+/// Every [SpatiallyIndexable] implements this as a bbox without extent, so point data does not have to care about it
+pub trait SpatiallyIndexableExtent<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    fn bbox(&self) -> BBox<C>;
+}
+
+impl<T, C> SpatiallyIndexableExtent<C> for T
+where
+    T: SpatiallyIndexable<C>,
+    C: Coordinate,
+{
+    fn bbox(&self) -> BBox<C> {
+        BBox::new(self.x(), self.y(), self.x(), self.y())
+    }
+}
+
+// implemented per coordinate type, a generic impl would conflict with the blanket impl above as downstream crates could implement SpatiallyIndexable for BBox of their own coordinate type
+macro_rules! impl_bbox_extent {
+    ($($t:ty),*) => {
+        $(
+            impl SpatiallyIndexableExtent<$t> for BBox<$t> {
+                fn bbox(&self) -> BBox<$t> {
+                    self.clone()
+                }
+            }
+        )*
+    };
+}
+
+impl_bbox_extent!(f32, f64, i32, i64);
+
+/// How the bbox of an element has to relate to the query envelope to be found by [query_with_predicate](struct.HPRTree.html#method.query_with_predicate)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpatialPredicate {
+    /// The element intersects the query envelope (this is what [query](struct.HPRTree.html#method.query) does)
+    Intersects,
+    /// The element fully contains the query envelope
+    Contains,
+    /// The element lies fully within the query envelope
+    Within,
+}
+
+/// The number of children per node unless set otherwise with [with_node_capacity](struct.HPRTreeBuilder.html#method.with_node_capacity)
+pub const DEFAULT_NODE_CAPACITY: usize = 16;
+/// The number of bits per axis used for the hilbert curve unless set otherwise with [with_hilbert_level](struct.HPRTreeBuilder.html#method.with_hilbert_level)
+pub const DEFAULT_HILBERT_LEVEL: u32 = 12;
+/// The highest supported hilbert level, 16 bits per axis make up a 32 bit hilbert key
+pub const MAX_HILBERT_LEVEL: u32 = 16;
+
+/// Maps points onto the grid of the hilbert curve, each axis spans its own range of the extent so neither ends up squeezed into or pushed outside of the grid
+pub(crate) struct HilbertGrid {
+    minx: f64,
+    miny: f64,
+    stride_x: f64,
+    stride_y: f64,
+    max_cell: f64,
+    hilbert_level: u32,
+}
+
+impl HilbertGrid {
+    pub fn new<C>(extent: &BBox<C>, hilbert_level: u32) -> Self
+    where
+        C: Coordinate,
+    {
+        let max_cell = ((1u32 << hilbert_level) - 1) as f64;
+        let stride = |size: f64| {
+            if size > 0f64 {
+                size / max_cell
+            } else {
+                1f64
+            }
+        };
+        Self {
+            minx: extent.minx.to_f64(),
+            miny: extent.miny.to_f64(),
+            stride_x: stride(extent.maxx.to_f64() - extent.minx.to_f64()),
+            stride_y: stride(extent.maxy.to_f64() - extent.miny.to_f64()),
+            max_cell,
+            hilbert_level,
+        }
+    }
+
+    /// Returns the hilbert index of the cell containing the point, points outside of the extent are clamped onto its border
+    pub fn index<C>(&self, point: &Point<C>) -> u32
+    where
+        C: Coordinate,
+    {
+        let x = ((point.x.to_f64() - self.minx) / self.stride_x).clamp(0f64, self.max_cell);
+        let y = ((point.y.to_f64() - self.miny) / self.stride_y).clamp(0f64, self.max_cell);
+        hilbert_xy_to_index(x as u32, y as u32, self.hilbert_level)
+    }
+}
 
 fn interleave(x: u32) -> u32 {
     let x = (x | (x << 8)) & 0x00FF00FF;
@@ -134,9 +374,9 @@ fn interleave(x: u32) -> u32 {
 }
 
 #[allow(non_snake_case)]
-fn hilbert_xy_to_index(x: u32, y: u32) -> u32 {
-    let x = x << (16 - HILBERT_LEVEL);
-    let y = y << (16 - HILBERT_LEVEL);
+fn hilbert_xy_to_index(x: u32, y: u32, hilbert_level: u32) -> u32 {
+    let x = x << (MAX_HILBERT_LEVEL - hilbert_level);
+    let y = y << (MAX_HILBERT_LEVEL - hilbert_level);
 
     let mut A: u32;
     let mut B: u32;
@@ -202,12 +442,332 @@ fn hilbert_xy_to_index(x: u32, y: u32) -> u32 {
     let i0 = x ^ y;
     let i1 = b | (0xFFFF ^ (i0 | a));
 
-    ((interleave(i1) << 1) | interleave(i0)) >> (32 - 2 * HILBERT_LEVEL)
+    ((interleave(i1) << 1) | interleave(i0)) >> (32 - 2 * hilbert_level)
+}
+
+/// A shape the tree can be queried with, this lets the different kinds of queries share the same traversal
+pub(crate) trait QueryShape<C>
+where
+    C: Coordinate,
+{
+    /// Checks whether elements within the given bbox may match the shape, used to prune nodes
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool;
+
+    /// Checks whether all elements within the given bbox match the shape, this allows accepting whole subtrees without testing each element
+    fn contains_bbox(&self, _bbox: &BBox<C>) -> bool {
+        false
+    }
+
+    /// Checks whether a single element (given by its bbox) matches the shape
+    fn matches_item(&self, item: &BBox<C>) -> bool;
+}
+
+impl<C> QueryShape<C> for BBox<C>
+where
+    C: Coordinate,
+{
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.intersects(bbox)
+    }
+
+    fn contains_bbox(&self, bbox: &BBox<C>) -> bool {
+        BBox::contains_bbox(self, bbox)
+    }
+
+    fn matches_item(&self, item: &BBox<C>) -> bool {
+        self.intersects(item)
+    }
+}
+
+/// A query envelope together with the spatial predicate elements have to satisfy
+pub(crate) struct PredicateQuery<'a, C>
+where
+    C: Coordinate,
+{
+    query_env: &'a BBox<C>,
+    predicate: SpatialPredicate,
+}
+
+impl<'a, C> PredicateQuery<'a, C>
+where
+    C: Coordinate,
+{
+    pub fn new(query_env: &'a BBox<C>, predicate: SpatialPredicate) -> Self {
+        Self {
+            query_env,
+            predicate,
+        }
+    }
+}
+
+impl<C> QueryShape<C> for PredicateQuery<'_, C>
+where
+    C: Coordinate,
+{
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool {
+        match self.predicate {
+            SpatialPredicate::Intersects | SpatialPredicate::Within => {
+                self.query_env.intersects(bbox)
+            }
+            // an element can only contain the query envelope if its node does as well
+            SpatialPredicate::Contains => bbox.contains_bbox(self.query_env),
+        }
+    }
+
+    fn contains_bbox(&self, bbox: &BBox<C>) -> bool {
+        match self.predicate {
+            SpatialPredicate::Intersects | SpatialPredicate::Within => {
+                self.query_env.contains_bbox(bbox)
+            }
+            SpatialPredicate::Contains => false,
+        }
+    }
+
+    fn matches_item(&self, item: &BBox<C>) -> bool {
+        match self.predicate {
+            SpatialPredicate::Intersects => self.query_env.intersects(item),
+            SpatialPredicate::Contains => item.contains_bbox(self.query_env),
+            SpatialPredicate::Within => self.query_env.contains_bbox(item),
+        }
+    }
+}
+
+/// A circle around a point with a radius measured by a distance metric
+pub(crate) struct DistanceQuery<'a, M, C>
+where
+    M: DistanceMetric,
+    C: Coordinate,
+{
+    center: Point<C>,
+    radius: f64,
+    metric: &'a M,
+}
+
+impl<'a, M, C> DistanceQuery<'a, M, C>
+where
+    M: DistanceMetric,
+    C: Coordinate,
+{
+    pub fn new(center: &Point<C>, radius: f64, metric: &'a M) -> Self {
+        Self {
+            center: center.clone(),
+            radius,
+            metric,
+        }
+    }
+}
+
+impl<M, C> QueryShape<C> for DistanceQuery<'_, M, C>
+where
+    M: DistanceMetric,
+    C: Coordinate,
+{
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.metric.min_distance_to_bbox(&self.center, bbox) <= self.radius
+    }
+
+    fn matches_item(&self, item: &BBox<C>) -> bool {
+        distance_to_item(self.metric, &self.center, item) <= self.radius
+    }
+}
+
+/// Returns the distance between a point and an element, elements without an extent use the exact point distance of the metric
+pub(crate) fn distance_to_item<C>(
+    metric: &impl DistanceMetric,
+    point: &Point<C>,
+    item: &BBox<C>,
+) -> f64
+where
+    C: Coordinate,
+{
+    if item.minx == item.maxx && item.miny == item.maxy {
+        metric.distance(point, &item.min_point())
+    } else {
+        metric.min_distance_to_bbox(point, item)
+    }
+}
+
+/// An entry of the priority queue used by the best-first nearest neighbour search
+///
+/// The ordering is reversed so that the std max-heap pops the closest candidate first
+pub(crate) struct NearestCandidate {
+    pub distance: f64,
+    pub kind: NearestCandidateKind,
+}
+
+pub(crate) enum NearestCandidateKind {
+    Node {
+        layer_index: usize,
+        node_offset: usize,
+    },
+    Item(usize),
+}
+
+impl PartialEq for NearestCandidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for NearestCandidate {}
+
+impl PartialOrd for NearestCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NearestCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+/// Returns the range of items below a node, which follows from every node having (up to) node_capacity children
+fn get_subtree_item_range(
+    layer_index: usize,
+    node_offset: usize,
+    item_count: usize,
+    node_capacity: usize,
+) -> Range<usize> {
+    let items_per_node = node_capacity.saturating_pow(layer_index as u32 + 1);
+    let start = (node_offset * items_per_node).min(item_count);
+    start..(start + items_per_node).min(item_count)
+}
+
+/// Returns the index of the first node of each layer (starting with the leaves) followed by the total number of nodes, trees with fewer elements than the node capacity have no nodes at all
+fn compute_layer_start_indices(item_count: usize, node_capacity: usize) -> Vec<usize> {
+    if item_count < node_capacity {
+        return Vec::new();
+    }
+
+    let mut node_count = item_count;
+    let mut layer_start_index =
+        Vec::with_capacity((item_count as f32).log(node_capacity as f32).ceil() as usize + 1);
+    let mut index: usize = 0;
+
+    loop {
+        layer_start_index.push(index);
+
+        node_count = node_count.div_ceil(node_capacity);
+        index += node_count;
+
+        if node_count <= 1 {
+            break;
+        }
+    }
+    layer_start_index.push(index);
+    layer_start_index
 }
 
 fn get_layer_size(layer: usize, layer_start_index: &[usize]) -> usize {
     layer_start_index[layer + 1] - layer_start_index[layer]
 }
 
-/// Internal type for coordinates
+/// Moves the elements and their ids (if the tree keeps any) into the given order, order\[i\] being the current index of whatever ends up at i
+fn reorder<T>(items: &mut [T], ids: &mut [usize], mut order: Vec<usize>) {
+    // follows each cycle of the permutation swapping the elements into place, the visited positions are marked by pointing to themselves
+    for start in 0..order.len() {
+        let mut current = start;
+        while order[current] != start {
+            let next = order[current];
+            items.swap(current, next);
+            if !ids.is_empty() {
+                ids.swap(current, next);
+            }
+            order[current] = current;
+            current = next;
+        }
+        order[current] = current;
+    }
+}
+
+/// Returns the positions of the elements ordered by their ids, which is as large as the ids themselves no matter how sparse they are
+fn sort_positions_by_id(ids: &[usize]) -> Vec<usize> {
+    let mut positions: Vec<usize> = (0..ids.len()).collect();
+    positions.sort_unstable_by_key(|position| ids[*position]);
+    positions
+}
+
+/// Looks up the position of the element with the given id in the positions ordered by [sort_positions_by_id]
+fn find_position(ids: &[usize], positions: &[usize], id: usize) -> Option<usize> {
+    positions
+        .binary_search_by_key(&id, |position| ids[*position])
+        .ok()
+        .map(|index| positions[index])
+}
+
+/// Expands the bounds of the leaf containing the element at the given position and of all nodes above it, so a moved element is still found by queries
+fn expand_ancestor_bounds<C>(
+    layer_start_index: &[usize],
+    node_bounds: &mut [BBox<C>],
+    node_capacity: usize,
+    position: usize,
+    bbox: &BBox<C>,
+) where
+    C: Coordinate,
+{
+    let mut node_offset = position;
+    for layer_start in layer_start_index
+        .iter()
+        .take(layer_start_index.len().saturating_sub(1))
+    {
+        node_offset /= node_capacity;
+        node_bounds[layer_start + node_offset].expand_to_include(bbox);
+    }
+}
+
+/// The share of removed or moved elements above which [needs_compaction](struct.HPRTree.html#method.needs_compaction) suggests to [compact](struct.HPRTree.html#method.compact) a tree
+pub const COMPACTION_THRESHOLD: f64 = 0.25;
+
+/// The id of an element, assigned in insertion order by the builders and kept stable through sorting and compaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemId(pub usize);
+
+/// Marks a builder that does not keep ids for its elements, which is the default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WithoutIds;
+
+/// Marks a builder that keeps ids for its elements and returns them from insert, see [with_ids](struct.HPRTreeBuilder.html#method.with_ids)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WithIds;
+
+/// A bitset marking removed elements by their position in a tree, it does not allocate until something is removed
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+struct Tombstones {
+    words: Vec<u64>,
+}
+
+impl Tombstones {
+    fn contains(&self, position: usize) -> bool {
+        self.words
+            .get(position / 64)
+            .is_some_and(|word| word & (1 << (position % 64)) != 0)
+    }
+
+    fn insert(&mut self, position: usize) {
+        if self.words.len() <= position / 64 {
+            self.words.resize(position / 64 + 1, 0);
+        }
+        self.words[position / 64] |= 1 << (position % 64);
+    }
+
+    fn count_in(&self, range: Range<usize>) -> usize {
+        if self.words.is_empty() {
+            return 0;
+        }
+        range.filter(|position| self.contains(*position)).count()
+    }
+
+    /// Checks that no position at or beyond the number of elements is marked
+    #[cfg(feature = "serde")]
+    fn fits(&self, item_count: usize) -> bool {
+        !(item_count..self.words.len() * 64).any(|position| self.contains(position))
+    }
+}
+
+/// The default type for coordinates, used whenever no other [Coordinate] type is specified
 pub type CoordinateType = f32;
@@ -0,0 +1,770 @@
+use std::{
+    collections::BinaryHeap,
+    fmt::{self, Display},
+    io::{self, Write},
+    marker::PhantomData,
+    ops::ControlFlow,
+};
+
+use crate::{
+    compute_layer_start_indices, distance_to_item, get_subtree_item_range, BBox, Coordinate,
+    CoordinateType, DistanceMetric, DistanceQuery, Euclidean, NearestCandidate,
+    NearestCandidateKind, Point, Polygon, QueryShape,
+};
+
+/// The magic bytes every mapped tree starts with
+pub const MAPPED_MAGIC: [u8; 8] = *b"HPRTREE\0";
+/// The version of the mapped format written by this crate, files of other versions are rejected
+pub const MAPPED_VERSION: u32 = 1;
+/// The size of the header in bytes
+pub const MAPPED_HEADER_SIZE: usize = 56;
+
+/// Trait for coordinate types that can be stored in a mapped tree
+pub trait MappableCoordinate: Coordinate {
+    /// Identifies the coordinate type in the header
+    const TAG: u32;
+    /// The number of bytes a coordinate takes up
+    const SIZE: usize;
+
+    /// Appends the coordinate as little-endian bytes
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// Reads the coordinate from the first SIZE little-endian bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_mappable_coordinate {
+    ($t:ty, $tag:expr) => {
+        impl MappableCoordinate for $t {
+            const TAG: u32 = $tag;
+            const SIZE: usize = std::mem::size_of::<$t>();
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(
+                    bytes[..<Self as MappableCoordinate>::SIZE]
+                        .try_into()
+                        .unwrap(),
+                )
+            }
+        }
+    };
+}
+
+impl_mappable_coordinate!(f32, 0);
+impl_mappable_coordinate!(f64, 1);
+impl_mappable_coordinate!(i32, 2);
+impl_mappable_coordinate!(i64, 3);
+
+/// Trait for elements that can be stored in the item section of a mapped tree, every element takes up the same number of bytes
+pub trait MappableItem {
+    /// The number of bytes each element takes up
+    const SIZE: usize;
+
+    /// Writes the element into a buffer of SIZE bytes
+    fn write_bytes(&self, buf: &mut [u8]);
+
+    /// Reads the element from a buffer of SIZE bytes
+    fn read_bytes(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_mappable_item {
+    ($($t:ty),*) => {
+        $(
+            impl MappableItem for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn write_bytes(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_bytes(buf: &[u8]) -> Self {
+                    <$t>::from_le_bytes(buf.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_mappable_item!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl MappableItem for usize {
+    // stored as u64 so files are the same on every platform
+    const SIZE: usize = 8;
+
+    fn write_bytes(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(&(*self as u64).to_le_bytes());
+    }
+
+    fn read_bytes(buf: &[u8]) -> Self {
+        u64::from_le_bytes(buf.try_into().unwrap()) as usize
+    }
+}
+
+impl MappableItem for () {
+    const SIZE: usize = 0;
+
+    fn write_bytes(&self, _buf: &mut [u8]) {}
+
+    fn read_bytes(_buf: &[u8]) -> Self {}
+}
+
+/// The reason why bytes could not be opened as a mapped tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MappedTreeError {
+    /// The bytes do not start with [MAPPED_MAGIC]
+    Magic,
+    /// The format version is not [MAPPED_VERSION]
+    Version(u32),
+    /// The tree was written with another coordinate type
+    CoordinateType { expected: u32, found: u32 },
+    /// The node capacity is smaller than 2
+    NodeCapacity(u32),
+    /// The elements were written with another size than the one of the type they are read as
+    ItemSize { expected: usize, found: usize },
+    /// The number of layers or nodes does not match the number of elements and the node capacity
+    Structure,
+    /// The number of bytes does not match what the header describes
+    Length { expected: usize, found: usize },
+    /// The checksum of the data does not match the one in the header
+    Checksum { expected: u64, found: u64 },
+}
+
+impl Display for MappedTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic => write!(f, "not a mapped hprtree, magic bytes do not match"),
+            Self::Version(version) => write!(
+                f,
+                "unsupported format version {version}, expected {MAPPED_VERSION}"
+            ),
+            Self::CoordinateType { expected, found } => {
+                write!(f, "expected coordinate type {expected}, found {found}")
+            }
+            Self::NodeCapacity(node_capacity) => {
+                write!(
+                    f,
+                    "invalid node capacity {node_capacity}, has to be at least 2"
+                )
+            }
+            Self::ItemSize { expected, found } => {
+                write!(f, "expected elements of {expected} bytes, found {found}")
+            }
+            Self::Structure => write!(
+                f,
+                "layers and nodes do not match the number of elements and node capacity"
+            ),
+            Self::Length { expected, found } => {
+                write!(f, "expected {expected} bytes, found {found}")
+            }
+            Self::Checksum { expected, found } => {
+                write!(
+                    f,
+                    "checksum mismatch, expected {expected:#x}, found {found:#x}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MappedTreeError {}
+
+/// FNV-1a, which is good enough to detect truncated or corrupted files
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn write_bbox<C>(bbox: &BBox<C>, out: &mut Vec<u8>)
+where
+    C: MappableCoordinate,
+{
+    bbox.minx.write_le(out);
+    bbox.miny.write_le(out);
+    bbox.maxx.write_le(out);
+    bbox.maxy.write_le(out);
+}
+
+fn read_bbox<C>(bytes: &[u8]) -> BBox<C>
+where
+    C: MappableCoordinate,
+{
+    BBox {
+        minx: C::read_le(bytes),
+        miny: C::read_le(&bytes[C::SIZE..]),
+        maxx: C::read_le(&bytes[2 * C::SIZE..]),
+        maxy: C::read_le(&bytes[3 * C::SIZE..]),
+    }
+}
+
+/// Writes the parts of a tree in the mapped format, shared by [HPRTree::write_mapped](struct.HPRTree.html#method.write_mapped) and [HPRTreeWrapping::write_mapped](struct.HPRTreeWrapping.html#method.write_mapped)
+pub(crate) fn write_mapped<I, C, P>(
+    writer: &mut impl Write,
+    items: &[I],
+    item_parts: impl Fn(&I) -> (BBox<C>, &P),
+    extent: &BBox<C>,
+    layer_start_index: &[usize],
+    node_bounds: &[BBox<C>],
+    node_capacity: usize,
+) -> io::Result<()>
+where
+    C: MappableCoordinate,
+    P: MappableItem,
+{
+    let bbox_size = 4 * C::SIZE;
+    let mut body = Vec::with_capacity(
+        bbox_size
+            + layer_start_index.len() * 8
+            + node_bounds.len() * bbox_size
+            + items.len() * (bbox_size + P::SIZE),
+    );
+    write_bbox(extent, &mut body);
+    for layer_start in layer_start_index {
+        body.extend_from_slice(&(*layer_start as u64).to_le_bytes());
+    }
+    for bounds in node_bounds {
+        write_bbox(bounds, &mut body);
+    }
+    for item in items {
+        let (bbox, payload) = item_parts(item);
+        write_bbox(&bbox, &mut body);
+        let start = body.len();
+        body.resize(start + P::SIZE, 0);
+        payload.write_bytes(&mut body[start..]);
+    }
+
+    let mut header = Vec::with_capacity(MAPPED_HEADER_SIZE);
+    header.extend_from_slice(&MAPPED_MAGIC);
+    header.extend_from_slice(&MAPPED_VERSION.to_le_bytes());
+    header.extend_from_slice(&C::TAG.to_le_bytes());
+    header.extend_from_slice(&(node_capacity as u32).to_le_bytes());
+    header.extend_from_slice(&(P::SIZE as u32).to_le_bytes());
+    header.extend_from_slice(&(items.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(layer_start_index.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(node_bounds.len() as u64).to_le_bytes());
+    header.extend_from_slice(&checksum(&body).to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(&body)
+}
+
+/// An element of a mapped tree, borrowing its data from the underlying bytes
+#[derive(Clone, Debug)]
+pub struct MappedItem<'a, T, C = CoordinateType>
+where
+    C: Coordinate,
+{
+    bbox: BBox<C>,
+    data: &'a [u8],
+    item: PhantomData<T>,
+}
+
+impl<'a, T, C> MappedItem<'a, T, C>
+where
+    T: MappableItem,
+    C: Coordinate,
+{
+    /// Returns the bbox the element was indexed by
+    pub fn bbox(&self) -> BBox<C> {
+        self.bbox.clone()
+    }
+
+    /// Returns the bytes the element was written as
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Reads the element, its size was checked against the file when the tree was opened
+    pub fn get(&self) -> T {
+        T::read_bytes(self.data)
+    }
+}
+
+/// A read-only tree that is queried directly on the bytes written by [HPRTree::write_mapped](struct.HPRTree.html#method.write_mapped), e.g. from a memory mapped file
+///
+/// The tree is opened as the element type it was written with, which is checked against the item size in the header so reading elements never fails
+///
+/// The layout is little-endian throughout and versioned by [MAPPED_VERSION], coordinates take up 4 or 8 bytes depending on their type:
+///
+/// | section | content |
+/// |---|---|
+/// | header | magic (8 bytes), version (u32), coordinate type (u32), node capacity (u32), item size (u32), item count (u64), layer count (u64), node count (u64), checksum of everything after the header (u64) |
+/// | extent | minx, miny, maxx, maxy |
+/// | layer offsets | the index of the first node of each layer starting with the leaves, followed by the node count (u64 each) |
+/// | node bounds | minx, miny, maxx, maxy for each node, layer after layer |
+/// | items | minx, miny, maxx, maxy and item size bytes for each element, in hilbert order |
+pub struct MappedHPRTree<'a, T, C = CoordinateType>
+where
+    T: MappableItem,
+    C: MappableCoordinate,
+{
+    extent: BBox<C>,
+    node_capacity: usize,
+    item_size: usize,
+    item_count: usize,
+    layer_start_index: &'a [u8],
+    node_bounds: &'a [u8],
+    items: &'a [u8],
+    body: &'a [u8],
+    checksum: u64,
+    item: PhantomData<T>,
+}
+
+impl<'a, T, C> MappedHPRTree<'a, T, C>
+where
+    T: MappableItem,
+    C: MappableCoordinate,
+{
+    /// Opens the bytes as a tree of T, checking the header, the element size and the size of each section without copying anything
+    ///
+    /// Only the header and the layer offsets are read, so the pages of a memory mapped file are loaded as queries touch them. Use [verify_checksum](struct.MappedHPRTree.html#method.verify_checksum) to detect corrupted files that are not trusted
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, MappedTreeError> {
+        if bytes.len() < MAPPED_HEADER_SIZE {
+            return Err(MappedTreeError::Length {
+                expected: MAPPED_HEADER_SIZE,
+                found: bytes.len(),
+            });
+        }
+        if bytes[..8] != MAPPED_MAGIC {
+            return Err(MappedTreeError::Magic);
+        }
+        let version = read_u32(bytes, 8);
+        if version != MAPPED_VERSION {
+            return Err(MappedTreeError::Version(version));
+        }
+        let tag = read_u32(bytes, 12);
+        if tag != C::TAG {
+            return Err(MappedTreeError::CoordinateType {
+                expected: C::TAG,
+                found: tag,
+            });
+        }
+        let node_capacity = read_u32(bytes, 16);
+        if node_capacity < 2 {
+            return Err(MappedTreeError::NodeCapacity(node_capacity));
+        }
+        let node_capacity = node_capacity as usize;
+        let item_size = read_u32(bytes, 20) as usize;
+        if item_size != T::SIZE {
+            return Err(MappedTreeError::ItemSize {
+                expected: T::SIZE,
+                found: item_size,
+            });
+        }
+        let item_count = read_u64(bytes, 24) as usize;
+        let layer_count = read_u64(bytes, 32) as usize;
+        let node_count = read_u64(bytes, 40) as usize;
+
+        let bbox_size = 4 * C::SIZE;
+        // the counts come from the file, so a corrupted header must neither overflow the offsets nor make the layers huge
+        if item_count > bytes.len() / bbox_size {
+            return Err(MappedTreeError::Structure);
+        }
+        let layer_start_index = compute_layer_start_indices(item_count, node_capacity);
+        if layer_start_index.len() != layer_count
+            || layer_start_index.last().copied().unwrap_or(0) != node_count
+        {
+            return Err(MappedTreeError::Structure);
+        }
+
+        let layers_start = MAPPED_HEADER_SIZE + bbox_size;
+        let nodes_start = layer_count
+            .checked_mul(8)
+            .and_then(|layers_size| layers_size.checked_add(layers_start))
+            .ok_or(MappedTreeError::Structure)?;
+        let items_start = node_count
+            .checked_mul(bbox_size)
+            .and_then(|nodes_size| nodes_size.checked_add(nodes_start))
+            .ok_or(MappedTreeError::Structure)?;
+        let expected = item_count
+            .checked_mul(bbox_size + item_size)
+            .and_then(|items_size| items_size.checked_add(items_start))
+            .ok_or(MappedTreeError::Structure)?;
+        if bytes.len() != expected {
+            return Err(MappedTreeError::Length {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let tree = Self {
+            extent: read_bbox(&bytes[MAPPED_HEADER_SIZE..]),
+            node_capacity,
+            item_size,
+            item_count,
+            layer_start_index: &bytes[layers_start..nodes_start],
+            node_bounds: &bytes[nodes_start..items_start],
+            items: &bytes[items_start..],
+            body: &bytes[MAPPED_HEADER_SIZE..],
+            checksum: read_u64(bytes, 48),
+            item: PhantomData,
+        };
+        if (0..layer_count).any(|layer| tree.layer_start(layer) != layer_start_index[layer]) {
+            return Err(MappedTreeError::Structure);
+        }
+        Ok(tree)
+    }
+
+    /// Checks the checksum in the header against the data, which reads every byte of the tree
+    pub fn verify_checksum(&self) -> Result<(), MappedTreeError> {
+        let found = checksum(self.body);
+        if self.checksum != found {
+            return Err(MappedTreeError::Checksum {
+                expected: self.checksum,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    fn layer_start(&self, layer_index: usize) -> usize {
+        read_u64(self.layer_start_index, layer_index * 8) as usize
+    }
+
+    fn layer_count(&self) -> usize {
+        self.layer_start_index.len() / 8
+    }
+
+    fn node_bbox(&self, node_index: usize) -> BBox<C> {
+        read_bbox(&self.node_bounds[node_index * 4 * C::SIZE..])
+    }
+
+    fn item(&self, item_index: usize) -> MappedItem<'a, T, C> {
+        let bbox_size = 4 * C::SIZE;
+        let start = item_index * (bbox_size + self.item_size);
+        MappedItem {
+            bbox: read_bbox(&self.items[start..]),
+            data: &self.items[start + bbox_size..start + bbox_size + self.item_size],
+            item: PhantomData,
+        }
+    }
+
+    fn query_node_children(
+        &self,
+        layer_index: usize,
+        block_offset: &usize,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let layer_start = self.layer_start(layer_index);
+        let layer_end = self.layer_start(layer_index + 1);
+        for i in 0..self.node_capacity {
+            let node_offset = block_offset + i;
+            if node_offset + layer_start >= layer_end {
+                return ControlFlow::Continue(());
+            }
+            self.query_node(&layer_index, &node_offset, query_shape, visitor)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn query_items(
+        &self,
+        block_start: usize,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        for i in 0..self.node_capacity {
+            let item_index = block_start + i;
+            if item_index >= self.item_count {
+                return ControlFlow::Continue(());
+            }
+            let current_item = self.item(item_index);
+            if query_shape.matches_item(&current_item.bbox) {
+                visitor(current_item)?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn query_node(
+        &self,
+        layer_index: &usize,
+        node_offset: &usize,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let layer_start = self.layer_start(*layer_index);
+        let node_bounds = self.node_bbox(layer_start + *node_offset);
+
+        if !query_shape.intersects_bbox(&node_bounds) {
+            return ControlFlow::Continue(());
+        }
+        if query_shape.contains_bbox(&node_bounds) {
+            let item_range = get_subtree_item_range(
+                *layer_index,
+                *node_offset,
+                self.item_count,
+                self.node_capacity,
+            );
+            for item_index in item_range {
+                visitor(self.item(item_index))?;
+            }
+            return ControlFlow::Continue(());
+        }
+        let child_node_offset = node_offset * self.node_capacity;
+        if *layer_index != 0 {
+            self.query_node_children(*layer_index - 1, &child_node_offset, query_shape, visitor)
+        } else {
+            self.query_items(child_node_offset, query_shape, visitor)
+        }
+    }
+
+    fn visit_shape(
+        &self,
+        query_shape: &impl QueryShape<C>,
+        visitor: &mut impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        if !query_shape.intersects_bbox(&self.extent) {
+            return ControlFlow::Continue(());
+        }
+
+        if self.layer_count() == 0 {
+            return self.query_items(0, query_shape, visitor);
+        }
+
+        let layer_index = self.layer_count() - 2;
+        let layer_size = self.layer_start(layer_index + 1) - self.layer_start(layer_index);
+
+        for i in 0..layer_size {
+            self.query_node(&layer_index, &i, query_shape, visitor)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Queries the tree by bounding box calling the visitor for each found element, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn query_visit(
+        &self,
+        query_env: &BBox<C>,
+        mut visitor: impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(query_env, &mut visitor)
+    }
+
+    /// Queries the tree by polygon calling the visitor for each element within it, the traversal stops as soon as the visitor returns [ControlFlow::Break]
+    pub fn query_visit_polygon(
+        &self,
+        polygon: &Polygon<C>,
+        mut visitor: impl FnMut(MappedItem<'a, T, C>) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.visit_shape(polygon, &mut visitor)
+    }
+
+    /// Queries the tree by bounding box returning a Vec of the found elements
+    pub fn query(&self, query_env: &BBox<C>) -> Vec<MappedItem<'a, T, C>> {
+        let mut candidate_list = Vec::new();
+        let _ = self.query_visit(query_env, |item| {
+            candidate_list.push(item);
+            ControlFlow::Continue(())
+        });
+        candidate_list
+    }
+
+    /// Queries the tree for all elements within a given (euclidean) distance of a point, returning a Vec of the found elements
+    pub fn query_within_distance(
+        &self,
+        center: &Point<C>,
+        radius: f64,
+    ) -> Vec<MappedItem<'a, T, C>> {
+        self.query_within_distance_with_metric(center, radius, &Euclidean)
+    }
+
+    /// Queries the tree for all elements within a given distance of a point as measured by the given metric, returning a Vec of the found elements
+    pub fn query_within_distance_with_metric(
+        &self,
+        center: &Point<C>,
+        radius: f64,
+        metric: &impl DistanceMetric,
+    ) -> Vec<MappedItem<'a, T, C>> {
+        let mut candidate_list = Vec::new();
+        let _ = self.visit_shape(&DistanceQuery::new(center, radius, metric), &mut |item| {
+            candidate_list.push(item);
+            ControlFlow::Continue(())
+        });
+        candidate_list
+    }
+
+    fn push_nearest_node_children(
+        &self,
+        layer_index: usize,
+        block_offset: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        let layer_start = self.layer_start(layer_index);
+        let layer_end = self.layer_start(layer_index + 1);
+        for i in 0..self.node_capacity {
+            let node_offset = block_offset + i;
+            if node_offset + layer_start >= layer_end {
+                return;
+            }
+            self.push_nearest_node(layer_index, node_offset, point, metric, queue);
+        }
+    }
+
+    fn push_nearest_node(
+        &self,
+        layer_index: usize,
+        node_offset: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        let node_index = self.layer_start(layer_index) + node_offset;
+        queue.push(NearestCandidate {
+            distance: metric.min_distance_to_bbox(point, &self.node_bbox(node_index)),
+            kind: NearestCandidateKind::Node {
+                layer_index,
+                node_offset,
+            },
+        });
+    }
+
+    fn push_nearest_items(
+        &self,
+        block_start: usize,
+        point: &Point<C>,
+        metric: &impl DistanceMetric,
+        queue: &mut BinaryHeap<NearestCandidate>,
+    ) {
+        for i in 0..self.node_capacity {
+            let item_index = block_start + i;
+            if item_index >= self.item_count {
+                return;
+            }
+            queue.push(NearestCandidate {
+                distance: distance_to_item(metric, point, &self.item(item_index).bbox),
+                kind: NearestCandidateKind::Item(item_index),
+            });
+        }
+    }
+
+    /// Finds the k elements closest to a point (by euclidean distance), returned in order of increasing distance
+    ///
+    /// The nodes are visited best-first, so only the pages holding the parts of the tree that may contain one of the k closest elements are read
+    pub fn nearest_k(&self, point: &Point<C>, k: usize) -> Vec<MappedItem<'a, T, C>> {
+        self.nearest_k_with_metric(point, k, &Euclidean)
+    }
+
+    /// Finds the k elements closest to a point as measured by the given metric, returned in order of increasing distance
+    pub fn nearest_k_with_metric(
+        &self,
+        point: &Point<C>,
+        k: usize,
+        metric: &impl DistanceMetric,
+    ) -> Vec<MappedItem<'a, T, C>> {
+        let mut result = Vec::with_capacity(k.min(self.item_count));
+        if k == 0 {
+            return result;
+        }
+
+        let mut queue = BinaryHeap::new();
+        if self.layer_count() == 0 {
+            self.push_nearest_items(0, point, metric, &mut queue);
+        } else {
+            let layer_index = self.layer_count() - 2;
+            let layer_size = self.layer_start(layer_index + 1) - self.layer_start(layer_index);
+
+            for i in 0..layer_size {
+                self.push_nearest_node(layer_index, i, point, metric, &mut queue);
+            }
+        }
+
+        while let Some(candidate) = queue.pop() {
+            match candidate.kind {
+                NearestCandidateKind::Item(item_index) => {
+                    result.push(self.item(item_index));
+                    if result.len() == k {
+                        break;
+                    }
+                }
+                NearestCandidateKind::Node {
+                    layer_index,
+                    node_offset,
+                } => {
+                    let child_node_offset = node_offset * self.node_capacity;
+                    if layer_index != 0 {
+                        self.push_nearest_node_children(
+                            layer_index - 1,
+                            child_node_offset,
+                            point,
+                            metric,
+                            &mut queue,
+                        );
+                    } else {
+                        self.push_nearest_items(child_node_offset, point, metric, &mut queue);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Returns the number of elements in the tree
+    pub fn len(&self) -> usize {
+        self.item_count
+    }
+
+    /// Returns whether the tree is empty
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    /// Returns the extent of the tree
+    pub fn extent(&self) -> BBox<C> {
+        self.extent.clone()
+    }
+
+    /// Returns the number of children per node the tree was built with
+    pub fn node_capacity(&self) -> usize {
+        self.node_capacity
+    }
+
+    /// Returns the number of node layers, trees with fewer elements than the node capacity have none
+    pub fn depth(&self) -> usize {
+        self.layer_count().saturating_sub(1)
+    }
+
+    /// Returns the bounds of the nodes in a layer, layer 0 being the leaves right above the elements and [depth](#method.depth) - 1 the top layer
+    ///
+    /// Panics if the layer is not below the [depth](#method.depth)
+    pub fn layer_bounds(&self, layer: usize) -> impl ExactSizeIterator<Item = BBox<C>> + '_ {
+        assert!(layer < self.depth(), "layer {layer} out of range");
+        let bbox_size = 4 * C::SIZE;
+        let bounds = &self.node_bounds
+            [self.layer_start(layer) * bbox_size..self.layer_start(layer + 1) * bbox_size];
+        bounds.chunks_exact(bbox_size).map(read_bbox)
+    }
+
+    /// Returns the number of bytes each element takes up (not counting its bbox)
+    pub fn item_size(&self) -> usize {
+        self.item_size
+    }
+
+    /// Returns how many bytes the nodes take up, which are the layer offsets and the node bounds
+    pub fn nodes_size_in_bytes(&self) -> usize {
+        self.layer_start_index.len() + self.node_bounds.len()
+    }
+
+    /// Returns how many bytes the elements take up including their bboxes
+    pub fn items_size_in_bytes(&self) -> usize {
+        self.items.len()
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{BBox, Coordinate, Point};
+
+/// A way of measuring the distance between points, used by the distance based queries ([nearest_k_with_metric](struct.HPRTree.html#method.nearest_k_with_metric), [query_within_distance_with_metric](struct.HPRTree.html#method.query_within_distance_with_metric))
+pub trait DistanceMetric {
+    /// Returns the distance between two points
+    fn distance<C: Coordinate>(&self, a: &Point<C>, b: &Point<C>) -> f64;
+
+    /// Returns a lower bound for the distance between a point and any point within the bbox, this has to be 0 if the point is contained and must never be larger than the actual distance or nodes would be pruned incorrectly
+    fn min_distance_to_bbox<C: Coordinate>(&self, point: &Point<C>, bbox: &BBox<C>) -> f64;
+}
+
+/// The planar (euclidean) distance in coordinate units, this is what [nearest_k](struct.HPRTree.html#method.nearest_k) and [query_within_distance](struct.HPRTree.html#method.query_within_distance) use
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Euclidean;
+
+impl DistanceMetric for Euclidean {
+    fn distance<C: Coordinate>(&self, a: &Point<C>, b: &Point<C>) -> f64 {
+        a.distance_squared(b).sqrt()
+    }
+
+    fn min_distance_to_bbox<C: Coordinate>(&self, point: &Point<C>, bbox: &BBox<C>) -> f64 {
+        bbox.min_distance_squared(point).sqrt()
+    }
+}
+
+/// The great-circle distance on a sphere, for lat/lon coordinates in degrees with lat=y, lon=x
+///
+/// Distances are in the unit of the radius, which for [Haversine::default](#method.default) is meters on the earth
+#[derive(Clone, Copy, Debug)]
+pub struct Haversine {
+    pub radius: f64,
+}
+
+impl Haversine {
+    /// The mean radius of the earth in meters
+    pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+    /// Creates a haversine metric for a sphere with the given radius
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+
+    fn distance_from_haversine(&self, haversine: f64) -> f64 {
+        2f64 * self.radius * haversine.clamp(0f64, 1f64).sqrt().asin()
+    }
+}
+
+impl Default for Haversine {
+    /// Distances on the earth in meters
+    fn default() -> Self {
+        Self::new(Self::EARTH_RADIUS)
+    }
+}
+
+impl DistanceMetric for Haversine {
+    fn distance<C: Coordinate>(&self, a: &Point<C>, b: &Point<C>) -> f64 {
+        let lat_a = a.y.to_f64().to_radians();
+        let lat_b = b.y.to_f64().to_radians();
+        let haversine_lon = haversine((b.x.to_f64() - a.x.to_f64()).to_radians());
+        self.distance_from_haversine(haversine_partial(haversine_lon, lat_a.cos(), lat_a, lat_b))
+    }
+
+    fn min_distance_to_bbox<C: Coordinate>(&self, point: &Point<C>, bbox: &BBox<C>) -> f64 {
+        // the approach is the one used by geokdbush (https://github.com/mourner/geokdbush)
+        let lon = point.x.to_f64();
+        let lat = point.y.to_f64().to_radians();
+        let min_lat = bbox.miny.to_f64().to_radians();
+        let max_lat = bbox.maxy.to_f64().to_radians();
+
+        // the point is between the minimum and maximum longitude, so the closest point is straight north or south
+        if lon >= bbox.minx.to_f64() && lon <= bbox.maxx.to_f64() {
+            if lat < min_lat {
+                return self.distance_from_haversine(haversine(min_lat - lat));
+            }
+            if lat > max_lat {
+                return self.distance_from_haversine(haversine(lat - max_lat));
+            }
+            return 0f64;
+        }
+
+        // otherwise the closest point lies on the closer one of the two bounding meridians, the great circle through the point reaches its extremum latitude on that meridian at vertex_lat
+        let haversine_lon = haversine((lon - bbox.minx.to_f64()).to_radians())
+            .min(haversine((lon - bbox.maxx.to_f64()).to_radians()));
+        let cos_lat = lat.cos();
+        let vertex_lat = vertex_lat(lat, haversine_lon);
+
+        if vertex_lat > min_lat && vertex_lat < max_lat {
+            return self.distance_from_haversine(haversine_partial(
+                haversine_lon,
+                cos_lat,
+                lat,
+                vertex_lat,
+            ));
+        }
+
+        self.distance_from_haversine(
+            haversine_partial(haversine_lon, cos_lat, lat, min_lat).min(haversine_partial(
+                haversine_lon,
+                cos_lat,
+                lat,
+                max_lat,
+            )),
+        )
+    }
+}
+
+fn haversine(theta: f64) -> f64 {
+    let s = (theta / 2f64).sin();
+    s * s
+}
+
+fn haversine_partial(haversine_lon: f64, cos_lat_a: f64, lat_a: f64, lat_b: f64) -> f64 {
+    cos_lat_a * lat_b.cos() * haversine_lon + haversine(lat_a - lat_b)
+}
+
+fn vertex_lat(lat: f64, haversine_lon: f64) -> f64 {
+    let cos_lon = 1f64 - 2f64 * haversine_lon;
+    if cos_lon <= 0f64 {
+        return if lat > 0f64 {
+            std::f64::consts::FRAC_PI_2
+        } else {
+            -std::f64::consts::FRAC_PI_2
+        };
+    }
+    (lat.tan() / cos_lon).atan()
+}
@@ -0,0 +1,201 @@
+use crate::{BBox, Coordinate, CoordinateType, Point, QueryShape};
+
+/// A polygon given by its exterior ring and any number of holes, used for querying the index (see [query_polygon_with_holes](struct.HPRTree.html#method.query_polygon_with_holes))
+///
+/// Rings may be given closed (last point equal to the first) or open, the closing edge is implied either way
+#[derive(Clone, Debug)]
+pub struct Polygon<C = CoordinateType>
+where
+    C: Coordinate,
+{
+    exterior: Vec<Point<C>>,
+    holes: Vec<Vec<Point<C>>>,
+    bbox: BBox<C>,
+}
+
+/// How a bbox relates to a polygon
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Relation {
+    Disjoint,
+    Intersects,
+    Inside,
+}
+
+impl<C> Polygon<C>
+where
+    C: Coordinate,
+{
+    /// Creates a polygon without holes
+    pub fn new(exterior: Vec<Point<C>>) -> Self {
+        Self::with_holes(exterior, Vec::new())
+    }
+
+    /// Creates a polygon with holes
+    pub fn with_holes(exterior: Vec<Point<C>>, holes: Vec<Vec<Point<C>>>) -> Self {
+        let mut bbox = BBox::default();
+        for point in &exterior {
+            bbox.expand_to_include_point(point);
+        }
+        Self {
+            exterior,
+            holes,
+            bbox,
+        }
+    }
+
+    /// Returns the exterior ring
+    pub fn exterior(&self) -> &[Point<C>] {
+        &self.exterior
+    }
+
+    /// Returns the holes
+    pub fn holes(&self) -> &[Vec<Point<C>>] {
+        &self.holes
+    }
+
+    /// Returns the bbox of the polygon
+    pub fn bbox(&self) -> BBox<C> {
+        self.bbox.clone()
+    }
+
+    /// Checks if a given point lies within the polygon (and not within one of its holes), points exactly on the boundary may go either way
+    pub fn contains(&self, point: &Point<C>) -> bool {
+        if !self.bbox.contains(point) {
+            return false;
+        }
+        // even-odd rule over all rings, so crossing the boundary of a hole counts like leaving the polygon
+        let mut inside = false;
+        for ring in self.rings() {
+            for (a, b) in ring_edges(ring) {
+                if (a.y > point.y) != (b.y > point.y) {
+                    let t = (point.y.to_f64() - a.y.to_f64()) / (b.y.to_f64() - a.y.to_f64());
+                    let x = a.x.to_f64() + t * (b.x.to_f64() - a.x.to_f64());
+                    if point.x.to_f64() < x {
+                        inside = !inside;
+                    }
+                }
+            }
+        }
+        inside
+    }
+
+    fn rings(&self) -> impl Iterator<Item = &Vec<Point<C>>> {
+        std::iter::once(&self.exterior).chain(self.holes.iter())
+    }
+
+    fn classify(&self, bbox: &BBox<C>) -> Relation {
+        if !self.bbox.intersects(bbox) {
+            return Relation::Disjoint;
+        }
+        if self
+            .rings()
+            .flat_map(|ring| ring_edges(ring))
+            .any(|(a, b)| segment_intersects_bbox(a, b, bbox))
+        {
+            return Relation::Intersects;
+        }
+        // no edge passes through the bbox, so it lies either completely inside or completely outside
+        let corner = Point {
+            x: bbox.minx,
+            y: bbox.miny,
+        };
+        if self.contains(&corner) {
+            Relation::Inside
+        } else {
+            Relation::Disjoint
+        }
+    }
+}
+
+impl<C> From<Vec<Point<C>>> for Polygon<C>
+where
+    C: Coordinate,
+{
+    fn from(exterior: Vec<Point<C>>) -> Self {
+        Self::new(exterior)
+    }
+}
+
+impl<C> QueryShape<C> for Polygon<C>
+where
+    C: Coordinate,
+{
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.classify(bbox) != Relation::Disjoint
+    }
+
+    fn contains_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.classify(bbox) == Relation::Inside
+    }
+
+    fn matches_item(&self, item: &BBox<C>) -> bool {
+        self.classify(item) == Relation::Inside
+    }
+}
+
+/// The polygons of a multipolygon queried in a single traversal, an element within any of them matches once even where they overlap
+pub(crate) struct MultiPolygonQuery<'a, C>(pub(crate) &'a [Polygon<C>])
+where
+    C: Coordinate;
+
+impl<C> QueryShape<C> for MultiPolygonQuery<'_, C>
+where
+    C: Coordinate,
+{
+    fn intersects_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.0.iter().any(|polygon| polygon.intersects_bbox(bbox))
+    }
+
+    fn contains_bbox(&self, bbox: &BBox<C>) -> bool {
+        self.0.iter().any(|polygon| polygon.contains_bbox(bbox))
+    }
+
+    fn matches_item(&self, item: &BBox<C>) -> bool {
+        self.0.iter().any(|polygon| polygon.matches_item(item))
+    }
+}
+
+fn ring_edges<C>(ring: &[Point<C>]) -> impl Iterator<Item = (&Point<C>, &Point<C>)>
+where
+    C: Coordinate,
+{
+    ring.iter().zip(ring.iter().cycle().skip(1))
+}
+
+/// Liang-Barsky clipping of the segment against the (closed) bbox
+fn segment_intersects_bbox<C>(a: &Point<C>, b: &Point<C>, bbox: &BBox<C>) -> bool
+where
+    C: Coordinate,
+{
+    let dx = b.x.to_f64() - a.x.to_f64();
+    let dy = b.y.to_f64() - a.y.to_f64();
+    let mut t0 = 0f64;
+    let mut t1 = 1f64;
+
+    for (p, q) in [
+        (-dx, a.x.to_f64() - bbox.minx.to_f64()),
+        (dx, bbox.maxx.to_f64() - a.x.to_f64()),
+        (-dy, a.y.to_f64() - bbox.miny.to_f64()),
+        (dy, bbox.maxy.to_f64() - a.y.to_f64()),
+    ] {
+        if p == 0f64 {
+            if q < 0f64 {
+                return false;
+            }
+        } else {
+            let r = q / p;
+            if p < 0f64 {
+                if r > t1 {
+                    return false;
+                }
+                t0 = t0.max(r);
+            } else {
+                if r < t0 {
+                    return false;
+                }
+                t1 = t1.min(r);
+            }
+        }
+    }
+    true
+}
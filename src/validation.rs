@@ -0,0 +1,184 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    compute_layer_start_indices, sort_positions_by_id, BBox, Coordinate, Tombstones,
+    DEFAULT_HILBERT_LEVEL, MAX_HILBERT_LEVEL,
+};
+
+/// The reason why the parts of a tree (e.g. from deserialisation) do not make up a valid tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidTreeError {
+    /// The node capacity is smaller than 2
+    NodeCapacity(usize),
+    /// The layer start indices do not match the number of elements and the node capacity
+    LayerStartIndex,
+    /// The number of node bounds does not match the layer start indices
+    NodeBoundsLength { expected: usize, found: usize },
+    /// A node does not contain all of its children (or elements for the leaves)
+    NodeBounds { layer: usize, node: usize },
+    /// The extent does not contain all elements
+    Extent,
+    /// The ids are not one per element or not unique
+    Ids,
+    /// The removed elements are marked at positions past the elements
+    Tombstones,
+    /// The hilbert level is not within 1..=16
+    HilbertLevel(u32),
+}
+
+impl Display for InvalidTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeCapacity(node_capacity) => {
+                write!(
+                    f,
+                    "invalid node capacity {node_capacity}, has to be at least 2"
+                )
+            }
+            Self::LayerStartIndex => write!(
+                f,
+                "layer start indices do not match the number of elements and node capacity"
+            ),
+            Self::NodeBoundsLength { expected, found } => {
+                write!(f, "expected {expected} node bounds, found {found}")
+            }
+            Self::NodeBounds { layer, node } => write!(
+                f,
+                "node {node} of layer {layer} does not contain all of its children"
+            ),
+            Self::Extent => write!(f, "extent does not contain all elements"),
+            Self::Ids => write!(f, "ids are not unique or do not match the elements"),
+            Self::Tombstones => write!(f, "removed elements are marked past the elements"),
+            Self::HilbertLevel(hilbert_level) => write!(
+                f,
+                "invalid hilbert level {hilbert_level}, has to be within 1..=16"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTreeError {}
+
+/// Checks that the parts of a tree are consistent with each other, so queries on a tree put together from them return the same results as on the tree they were taken from
+pub(crate) fn validate_tree<I, C>(
+    items: &[I],
+    item_bbox: impl Fn(&I) -> BBox<C>,
+    extent: &BBox<C>,
+    layer_start_index: &[usize],
+    node_bounds: &[BBox<C>],
+    node_capacity: usize,
+) -> Result<(), InvalidTreeError>
+where
+    C: Coordinate,
+{
+    if node_capacity < 2 {
+        return Err(InvalidTreeError::NodeCapacity(node_capacity));
+    }
+    if layer_start_index != compute_layer_start_indices(items.len(), node_capacity) {
+        return Err(InvalidTreeError::LayerStartIndex);
+    }
+    let expected = layer_start_index.last().copied().unwrap_or(0);
+    if node_bounds.len() != expected {
+        return Err(InvalidTreeError::NodeBoundsLength {
+            expected,
+            found: node_bounds.len(),
+        });
+    }
+
+    if !items
+        .iter()
+        .all(|item| extent.contains_bbox(&item_bbox(item)))
+    {
+        return Err(InvalidTreeError::Extent);
+    }
+    if layer_start_index.is_empty() {
+        return Ok(());
+    }
+
+    let leaves = &node_bounds[..layer_start_index[1]];
+    for (node, (bounds, chunk)) in leaves.iter().zip(items.chunks(node_capacity)).enumerate() {
+        if !chunk
+            .iter()
+            .all(|item| bounds.contains_bbox(&item_bbox(item)))
+        {
+            return Err(InvalidTreeError::NodeBounds { layer: 0, node });
+        }
+    }
+    for layer in 1..(layer_start_index.len() - 1) {
+        let children = &node_bounds[layer_start_index[layer - 1]..layer_start_index[layer]];
+        let nodes = &node_bounds[layer_start_index[layer]..layer_start_index[layer + 1]];
+        for (node, (bounds, chunk)) in nodes.iter().zip(children.chunks(node_capacity)).enumerate()
+        {
+            if !chunk.iter().all(|child| bounds.contains_bbox(child)) {
+                return Err(InvalidTreeError::NodeBounds { layer, node });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the ids and removed elements of a tree, returning the ids (none for a tree built without them) along with the positions ordered by id, the number of removed elements and the number of ids handed out
+pub(crate) fn validate_ids(
+    ids: Vec<usize>,
+    tombstones: &Tombstones,
+    item_count: usize,
+    id_count: usize,
+    hilbert_level: u32,
+) -> Result<(Vec<usize>, Vec<usize>, usize, usize), InvalidTreeError> {
+    if !(1..=MAX_HILBERT_LEVEL).contains(&hilbert_level) {
+        return Err(InvalidTreeError::HilbertLevel(hilbert_level));
+    }
+    if !tombstones.fits(item_count) {
+        return Err(InvalidTreeError::Tombstones);
+    }
+    let removed = tombstones.count_in(0..item_count);
+    if ids.is_empty() {
+        return Ok((ids, Vec::new(), removed, id_count));
+    }
+    if ids.len() != item_count {
+        return Err(InvalidTreeError::Ids);
+    }
+    // nothing is allocated by the value of the ids, so sparse or huge ones cost no more than any others
+    let positions = sort_positions_by_id(&ids);
+    if positions
+        .windows(2)
+        .any(|pair| ids[pair[0]] == ids[pair[1]])
+    {
+        return Err(InvalidTreeError::Ids);
+    }
+    // trees serialised before the number of handed out ids was kept get it from their largest id
+    let id_count = ids[positions[positions.len() - 1]]
+        .checked_add(1)
+        .ok_or(InvalidTreeError::Ids)?
+        .max(id_count);
+    Ok((ids, positions, removed, id_count))
+}
+
+fn default_hilbert_level() -> u32 {
+    DEFAULT_HILBERT_LEVEL
+}
+
+/// The parts of a tree as they are serialised, deserialising a tree goes through this so the parts can be validated before they are put together
+///
+/// Trees serialised before elements had ids lack the ids, removed elements and hilbert level, which are filled in as a tree built without ids would have them
+#[derive(serde::Deserialize)]
+pub(crate) struct TreeData<I, C>
+where
+    C: Coordinate,
+{
+    pub items: Vec<I>,
+    #[serde(default)]
+    pub ids: Vec<usize>,
+    #[serde(default)]
+    pub id_count: usize,
+    #[serde(default)]
+    pub tombstones: Tombstones,
+    #[serde(default)]
+    pub stale: usize,
+    pub extent: BBox<C>,
+    pub layer_start_index: Vec<usize>,
+    pub node_bounds: Vec<BBox<C>>,
+    pub node_capacity: usize,
+    #[serde(default = "default_hilbert_level")]
+    pub hilbert_level: u32,
+}
@@ -0,0 +1,304 @@
+use std::fmt::{self, Display, Write};
+
+use crate::{Coordinate, Geometry, Point, Polygon};
+
+const WKB_POINT: u32 = 1;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTIPOLYGON: u32 = 6;
+
+// the flags of the extended WKB written by PostGIS, ISO WKB adds 1000, 2000 or 3000 to the type instead
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+/// The reason why WKB could not be read as a [Geometry](enum.Geometry.html), offsets are into the bytes (or the text for hex)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WkbError {
+    /// The bytes ended before the geometry was complete
+    UnexpectedEnd { offset: usize },
+    /// The byte order marker is neither 0 (big endian) nor 1 (little endian)
+    ByteOrder { offset: usize, found: u8 },
+    /// The geometry type is not a Point, Polygon or MultiPolygon (or a MultiPolygon contains something else than Polygons)
+    UnsupportedType { offset: usize, geometry_type: u32 },
+    /// A coordinate is not finite
+    NonFinite { offset: usize },
+    /// A ring has fewer than three points
+    TooFewPoints { offset: usize },
+    /// The geometry is empty (a point with NaN coordinates or a polygon without rings), so there is nothing to query with, a MultiPolygon without polygons is read as one
+    Empty { offset: usize },
+    /// There are more bytes after the geometry
+    TrailingBytes { offset: usize },
+    /// The text is not an even number of hex digits
+    InvalidHex { offset: usize },
+}
+
+impl Display for WkbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd { offset } => write!(f, "unexpected end of WKB at offset {offset}"),
+            Self::ByteOrder { offset, found } => {
+                write!(f, "invalid byte order {found} at offset {offset} of WKB")
+            }
+            Self::UnsupportedType {
+                offset,
+                geometry_type,
+            } => write!(
+                f,
+                "unsupported geometry type {geometry_type} at offset {offset} of WKB, expected a Point, Polygon or MultiPolygon"
+            ),
+            Self::NonFinite { offset } => {
+                write!(f, "non-finite coordinate at offset {offset} of WKB")
+            }
+            Self::TooFewPoints { offset } => write!(
+                f,
+                "the ring at offset {offset} of WKB has fewer than three points"
+            ),
+            Self::Empty { offset } => write!(f, "the geometry at offset {offset} of WKB is empty"),
+            Self::TrailingBytes { offset } => {
+                write!(f, "unexpected bytes after the geometry at offset {offset} of WKB")
+            }
+            Self::InvalidHex { offset } => write!(f, "invalid hex WKB at offset {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for WkbError {}
+
+struct WkbReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    little_endian: bool,
+}
+
+impl<'a> WkbReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], WkbError> {
+        let bytes =
+            self.bytes
+                .get(self.offset..self.offset + N)
+                .ok_or(WkbError::UnexpectedEnd {
+                    offset: self.bytes.len(),
+                })?;
+        self.offset += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, WkbError> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64, WkbError> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    /// Reads the byte order and one of the `allowed` types of a geometry, returns the type and the number of ordinates per position
+    fn header(&mut self, allowed: &[u32]) -> Result<(u32, usize), WkbError> {
+        let offset = self.offset;
+        let [byte_order] = self.take()?;
+        self.little_endian = match byte_order {
+            0 => false,
+            1 => true,
+            found => return Err(WkbError::ByteOrder { offset, found }),
+        };
+        let flags = self.u32()?;
+        if flags & EWKB_SRID != 0 {
+            // coordinates are taken as they are, whatever their reference system
+            self.u32()?;
+        }
+        let geometry_type = flags & 0x0fff_ffff;
+        let (geometry_type, iso_dimensions) = (geometry_type % 1000, geometry_type / 1000);
+        let (has_z, has_m) = match iso_dimensions {
+            0 => (flags & EWKB_Z != 0, flags & EWKB_M != 0),
+            1 => (true, false),
+            2 => (false, true),
+            3 => (true, true),
+            _ => {
+                return Err(WkbError::UnsupportedType {
+                    offset,
+                    geometry_type: flags,
+                })
+            }
+        };
+        if !allowed.contains(&geometry_type) {
+            return Err(WkbError::UnsupportedType {
+                offset,
+                geometry_type: flags,
+            });
+        }
+        Ok((geometry_type, 2 + has_z as usize + has_m as usize))
+    }
+
+    /// Reads the x and y of a position, any Z and M ordinates after them are skipped
+    fn point<C: Coordinate>(&mut self, ordinates: usize) -> Result<Point<C>, WkbError> {
+        let offset = self.offset;
+        let x = self.f64()?;
+        let y = self.f64()?;
+        for _ in 2..ordinates {
+            self.f64()?;
+        }
+        if x.is_nan() && y.is_nan() {
+            // POINT EMPTY
+            return Err(WkbError::Empty { offset });
+        }
+        if !x.is_finite() || !y.is_finite() {
+            return Err(WkbError::NonFinite { offset });
+        }
+        Ok(Point {
+            x: C::from_f64(x),
+            y: C::from_f64(y),
+        })
+    }
+
+    fn polygon<C: Coordinate>(&mut self, ordinates: usize) -> Result<Polygon<C>, WkbError> {
+        let offset = self.offset;
+        let num_rings = self.u32()?;
+        if num_rings == 0 {
+            return Err(WkbError::Empty { offset });
+        }
+        // the counts are not trusted for preallocating, a corrupt one runs into the end of the bytes instead
+        let mut rings = Vec::new();
+        for _ in 0..num_rings {
+            let offset = self.offset;
+            let num_points = self.u32()?;
+            if num_points < 3 {
+                return Err(WkbError::TooFewPoints { offset });
+            }
+            let ring = (0..num_points)
+                .map(|_| self.point(ordinates))
+                .collect::<Result<Vec<_>, _>>()?;
+            rings.push(ring);
+        }
+        let exterior = rings.remove(0);
+        Ok(Polygon::with_holes(exterior, rings))
+    }
+
+    fn geometry<C: Coordinate>(&mut self) -> Result<Geometry<C>, WkbError> {
+        let (geometry_type, ordinates) =
+            self.header(&[WKB_POINT, WKB_POLYGON, WKB_MULTIPOLYGON])?;
+        Ok(match geometry_type {
+            WKB_POINT => Geometry::Point(self.point(ordinates)?),
+            WKB_POLYGON => Geometry::Polygon(self.polygon(ordinates)?),
+            _ => {
+                let num_polygons = self.u32()?;
+                // a MultiPolygon without polygons is read as it is written, it simply finds nothing
+                let mut polygons = Vec::new();
+                for _ in 0..num_polygons {
+                    // every polygon has a header of its own, which may even switch the byte order
+                    let (_, ordinates) = self.header(&[WKB_POLYGON])?;
+                    polygons.push(self.polygon(ordinates)?);
+                }
+                Geometry::MultiPolygon(polygons)
+            }
+        })
+    }
+}
+
+fn write_rings<C: Coordinate>(wkb: &mut Vec<u8>, rings: &[Vec<Point<C>>]) {
+    wkb.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+    for ring in rings {
+        wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+        for point in ring {
+            wkb.extend_from_slice(&point.x.to_f64().to_le_bytes());
+            wkb.extend_from_slice(&point.y.to_f64().to_le_bytes());
+        }
+    }
+}
+
+fn write_header(wkb: &mut Vec<u8>, geometry_type: u32) {
+    wkb.push(1);
+    wkb.extend_from_slice(&geometry_type.to_le_bytes());
+}
+
+impl<C> Geometry<C>
+where
+    C: Coordinate,
+{
+    /// Reads a Point, Polygon or MultiPolygon from WKB in either byte order
+    ///
+    /// The extended WKB of PostGIS is accepted as well, an SRID and Z/M ordinates are dropped
+    pub fn from_wkb(bytes: &[u8]) -> Result<Self, WkbError> {
+        let mut reader = WkbReader {
+            bytes,
+            offset: 0,
+            little_endian: true,
+        };
+        let geometry = reader.geometry()?;
+        if reader.offset != bytes.len() {
+            return Err(WkbError::TrailingBytes {
+                offset: reader.offset,
+            });
+        }
+        Ok(geometry)
+    }
+
+    /// Reads WKB given as hex (e.g. the text form of a PostGIS geometry column), see [from_wkb](enum.Geometry.html#method.from_wkb)
+    ///
+    /// A leading `\x` as in the text form of a bytea is skipped
+    pub fn from_wkb_hex(text: &str) -> Result<Self, WkbError> {
+        let (skipped, hex) = match text.strip_prefix("\\x") {
+            Some(hex) => (2, hex.as_bytes()),
+            None => (0, text.as_bytes()),
+        };
+        let digit = |offset: usize| {
+            (hex[offset] as char)
+                .to_digit(16)
+                .ok_or(WkbError::InvalidHex {
+                    offset: skipped + offset,
+                })
+        };
+        if hex.len() % 2 != 0 {
+            return Err(WkbError::InvalidHex {
+                offset: skipped + hex.len(),
+            });
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|offset| Ok((digit(offset)? << 4 | digit(offset + 1)?) as u8))
+            .collect::<Result<Vec<u8>, WkbError>>()?;
+        Self::from_wkb(&bytes)
+    }
+
+    /// Writes the geometry as 2D little endian WKB, an envelope is written as a Polygon and rings are closed
+    pub fn to_wkb(&self) -> Vec<u8> {
+        let mut wkb = Vec::new();
+        match self {
+            Self::Point(point) => {
+                write_header(&mut wkb, WKB_POINT);
+                wkb.extend_from_slice(&point.x.to_f64().to_le_bytes());
+                wkb.extend_from_slice(&point.y.to_f64().to_le_bytes());
+            }
+            Self::Polygon(_) | Self::Envelope(_) => {
+                write_header(&mut wkb, WKB_POLYGON);
+                write_rings(&mut wkb, &self.polygon_rings()[0]);
+            }
+            Self::MultiPolygon(polygons) => {
+                write_header(&mut wkb, WKB_MULTIPOLYGON);
+                wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+                for rings in self.polygon_rings() {
+                    write_header(&mut wkb, WKB_POLYGON);
+                    write_rings(&mut wkb, &rings);
+                }
+            }
+        }
+        wkb
+    }
+
+    /// Writes the geometry as hex WKB in upper case like PostGIS, see [to_wkb](enum.Geometry.html#method.to_wkb)
+    pub fn to_wkb_hex(&self) -> String {
+        let wkb = self.to_wkb();
+        let mut hex = String::with_capacity(wkb.len() * 2);
+        for byte in wkb {
+            write!(hex, "{byte:02X}").unwrap();
+        }
+        hex
+    }
+}
@@ -0,0 +1,366 @@
+use std::fmt::{self, Display, Write};
+
+use crate::{BBox, Coordinate, Geometry, Point, Polygon};
+
+/// The reason why WKT could not be read as a [Geometry](enum.Geometry.html), positions are byte offsets into the text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WktError {
+    /// The text ended before the geometry was complete
+    UnexpectedEnd,
+    /// Something else than what was expected was found
+    Unexpected {
+        position: usize,
+        expected: &'static str,
+    },
+    /// The geometry type is not one of POINT, POLYGON, MULTIPOLYGON, ENVELOPE or BOX
+    UnsupportedType(String),
+    /// A coordinate is not a number or not finite
+    InvalidNumber { position: usize },
+    /// A ring has fewer than three points
+    TooFewPoints { position: usize },
+    /// The geometry is EMPTY, so there is nothing to query with (only `MULTIPOLYGON EMPTY` is read, as a MultiPolygon without polygons)
+    Empty,
+}
+
+impl Display for WktError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of WKT"),
+            Self::Unexpected { position, expected } => {
+                write!(f, "expected {expected} at position {position} of WKT")
+            }
+            Self::UnsupportedType(geometry_type) => write!(
+                f,
+                "unsupported geometry type {geometry_type}, expected POINT, POLYGON, MULTIPOLYGON, ENVELOPE or BOX"
+            ),
+            Self::InvalidNumber { position } => {
+                write!(f, "invalid coordinate at position {position} of WKT")
+            }
+            Self::TooFewPoints { position } => write!(
+                f,
+                "the ring at position {position} of WKT has fewer than three points"
+            ),
+            Self::Empty => write!(f, "the geometry is empty"),
+        }
+    }
+}
+
+impl std::error::Error for WktError {}
+
+struct WktParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> WktParser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn unexpected(&mut self, expected: &'static str) -> WktError {
+        match self.peek() {
+            Some(_) => WktError::Unexpected {
+                position: self.position,
+                expected,
+            },
+            None => WktError::UnexpectedEnd,
+        }
+    }
+
+    fn expect(&mut self, c: char, expected: &'static str) -> Result<(), WktError> {
+        if self.peek() != Some(c) {
+            return Err(self.unexpected(expected));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Consumes `c` if it is next
+    fn accept(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Reads a run of characters up to the next delimiter, which may be empty
+    fn token(&mut self, is_delimiter: impl Fn(char) -> bool) -> &'a str {
+        self.skip_whitespace();
+        let rest = &self.text[self.position..];
+        let len = rest.find(is_delimiter).unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    fn word(&mut self) -> &'a str {
+        self.token(|c| !c.is_ascii_alphanumeric() && c != '_')
+    }
+
+    fn number(&mut self) -> Result<f64, WktError> {
+        self.skip_whitespace();
+        let position = self.position;
+        let token = self.token(|c| c.is_whitespace() || matches!(c, ',' | '(' | ')' | ';'));
+        if token.is_empty() {
+            return Err(self.unexpected("a number"));
+        }
+        match token.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(WktError::InvalidNumber { position }),
+        }
+    }
+
+    /// Reads the x and y of a position, any Z and M ordinates after them are skipped
+    fn point<C: Coordinate>(&mut self) -> Result<Point<C>, WktError> {
+        let x = self.number()?;
+        let y = self.number()?;
+        for _ in 0..2 {
+            if matches!(self.peek(), Some(',' | ')') | None) {
+                break;
+            }
+            self.number()?;
+        }
+        Ok(Point {
+            x: C::from_f64(x),
+            y: C::from_f64(y),
+        })
+    }
+
+    /// Consumes `EMPTY` if it is next
+    fn accept_empty(&mut self) -> bool {
+        let position = self.position;
+        let found = self.word().eq_ignore_ascii_case("EMPTY");
+        if !found {
+            self.position = position;
+        }
+        found
+    }
+
+    /// Reads `EMPTY` or the opening parenthesis of a geometry
+    fn open(&mut self) -> Result<(), WktError> {
+        if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            let position = self.position;
+            if self.word().eq_ignore_ascii_case("EMPTY") {
+                return Err(WktError::Empty);
+            }
+            return Err(WktError::Unexpected {
+                position,
+                expected: "'(' or EMPTY",
+            });
+        }
+        self.expect('(', "'(' or EMPTY")
+    }
+
+    fn ring<C: Coordinate>(&mut self) -> Result<Vec<Point<C>>, WktError> {
+        self.skip_whitespace();
+        let position = self.position;
+        self.expect('(', "'('")?;
+        let mut ring = vec![self.point()?];
+        while self.accept(',') {
+            ring.push(self.point()?);
+        }
+        self.expect(')', "',' or ')'")?;
+        if ring.len() < 3 {
+            return Err(WktError::TooFewPoints { position });
+        }
+        Ok(ring)
+    }
+
+    /// Reads the rings of a polygon after its opening parenthesis
+    fn polygon<C: Coordinate>(&mut self) -> Result<Polygon<C>, WktError> {
+        let exterior = self.ring()?;
+        let mut holes = Vec::new();
+        while self.accept(',') {
+            holes.push(self.ring()?);
+        }
+        self.expect(')', "',' or ')'")?;
+        Ok(Polygon::with_holes(exterior, holes))
+    }
+
+    fn geometry<C: Coordinate>(&mut self) -> Result<Geometry<C>, WktError> {
+        // the SRID of EWKT as written by PostGIS is skipped, coordinates are taken as they are
+        let start = self.position;
+        if self.word().eq_ignore_ascii_case("SRID") {
+            self.expect('=', "'='")?;
+            self.token(|c| c == ';');
+            self.expect(';', "';'")?;
+        } else {
+            self.position = start;
+        }
+
+        let geometry_type = self.word().to_ascii_uppercase();
+        if geometry_type.is_empty() {
+            return Err(self.unexpected("a geometry type"));
+        }
+        if !matches!(
+            geometry_type.as_str(),
+            "POINT" | "POLYGON" | "MULTIPOLYGON" | "ENVELOPE" | "BOX"
+        ) {
+            return Err(WktError::UnsupportedType(geometry_type));
+        }
+        // the dimension of the coordinates, only x and y are used
+        if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            let position = self.position;
+            let dimension = self.word();
+            if !["Z", "M", "ZM"]
+                .iter()
+                .any(|d| d.eq_ignore_ascii_case(dimension))
+            {
+                // EMPTY is handled when looking for the opening parenthesis
+                self.position = position;
+            }
+        }
+
+        // a MULTIPOLYGON without polygons is what one is written as, it simply finds nothing
+        if geometry_type == "MULTIPOLYGON" && self.accept_empty() {
+            if self.peek().is_some() {
+                return Err(self.unexpected("the end of the WKT"));
+            }
+            return Ok(Geometry::MultiPolygon(Vec::new()));
+        }
+        self.open()?;
+        let geometry = match geometry_type.as_str() {
+            "POINT" => {
+                let point = self.point()?;
+                self.expect(')', "')'")?;
+                Geometry::Point(point)
+            }
+            "POLYGON" => Geometry::Polygon(self.polygon()?),
+            "MULTIPOLYGON" => {
+                let mut polygons = Vec::new();
+                loop {
+                    self.open()?;
+                    polygons.push(self.polygon()?);
+                    if !self.accept(',') {
+                        break;
+                    }
+                }
+                self.expect(')', "',' or ')'")?;
+                Geometry::MultiPolygon(polygons)
+            }
+            // ENVELOPE(minx, maxx, maxy, miny) as used by CQL and Solr
+            "ENVELOPE" => {
+                let minx = self.number()?;
+                self.expect(',', "','")?;
+                let maxx = self.number()?;
+                self.expect(',', "','")?;
+                let maxy = self.number()?;
+                self.expect(',', "','")?;
+                let miny = self.number()?;
+                self.expect(')', "')'")?;
+                Geometry::Envelope(envelope(minx, miny, maxx, maxy))
+            }
+            // BOX(minx miny, maxx maxy) as written for a box2d by PostGIS
+            _ => {
+                let min: Point<f64> = self.point()?;
+                self.expect(',', "','")?;
+                let max: Point<f64> = self.point()?;
+                self.expect(')', "')'")?;
+                Geometry::Envelope(envelope(min.x, min.y, max.x, max.y))
+            }
+        };
+        if self.peek().is_some() {
+            return Err(self.unexpected("the end of the WKT"));
+        }
+        Ok(geometry)
+    }
+}
+
+/// Creates a bbox from corners given in any order
+fn envelope<C: Coordinate>(x1: f64, y1: f64, x2: f64, y2: f64) -> BBox<C> {
+    BBox::new(
+        C::from_f64(x1.min(x2)),
+        C::from_f64(y1.min(y2)),
+        C::from_f64(x1.max(x2)),
+        C::from_f64(y1.max(y2)),
+    )
+}
+
+fn write_ring<C: Coordinate>(f: &mut impl Write, ring: &[Point<C>]) -> fmt::Result {
+    f.write_char('(')?;
+    for (i, point) in ring.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{:?} {:?}", point.x, point.y)?;
+    }
+    f.write_char(')')
+}
+
+fn write_polygon<C: Coordinate>(f: &mut impl Write, rings: &[Vec<Point<C>>]) -> fmt::Result {
+    f.write_char('(')?;
+    for (i, ring) in rings.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write_ring(f, ring)?;
+    }
+    f.write_char(')')
+}
+
+impl<C> Geometry<C>
+where
+    C: Coordinate,
+{
+    /// Reads a POINT, POLYGON, MULTIPOLYGON, ENVELOPE or BOX from WKT
+    ///
+    /// Keywords are case insensitive, an EWKT `SRID=...;` prefix and Z/M ordinates are accepted and dropped
+    ///
+    /// ```
+    /// use hprtree::{Geometry, HPRTreeBuilder, Point};
+    ///
+    /// let mut builder = HPRTreeBuilder::new(2);
+    /// builder.insert(Point { x: 1f64, y: 1f64 });
+    /// builder.insert(Point { x: 5f64, y: 5f64 });
+    /// let index = builder.build();
+    ///
+    /// let Ok(Geometry::Polygon(polygon)) = Geometry::from_wkt("POLYGON((0 0, 3 0, 3 3, 0 3, 0 0))") else {
+    ///     panic!("expected a polygon");
+    /// };
+    /// let result = index.query_polygon_with_holes(&polygon);
+    /// assert!(result.len() == 1);
+    /// assert!(Geometry::from(result[0].clone()).to_wkt() == "POINT(1.0 1.0)");
+    /// ```
+    pub fn from_wkt(text: &str) -> Result<Self, WktError> {
+        WktParser { text, position: 0 }.geometry()
+    }
+
+    /// Writes the geometry as WKT, an envelope is written as a POLYGON and rings are closed
+    pub fn to_wkt(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<C> Display for Geometry<C>
+where
+    C: Coordinate,
+{
+    /// Writes the geometry as WKT, see [to_wkt](enum.Geometry.html#method.to_wkt)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let polygons = self.polygon_rings();
+        match self {
+            Self::Point(point) => write!(f, "POINT({:?} {:?})", point.x, point.y),
+            Self::Polygon(_) | Self::Envelope(_) => {
+                f.write_str("POLYGON")?;
+                write_polygon(f, &polygons[0])
+            }
+            Self::MultiPolygon(_) if polygons.is_empty() => f.write_str("MULTIPOLYGON EMPTY"),
+            Self::MultiPolygon(_) => {
+                f.write_str("MULTIPOLYGON(")?;
+                for (i, rings) in polygons.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_polygon(f, rings)?;
+                }
+                f.write_char(')')
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Fixtures shared by the integration tests, each test crate only uses some of them
+#![allow(dead_code)]
+
+use hprtree::{
+    BBox, Coordinate, CoordinateType, MappableItem, Point, SpatiallyIndexable, SpatiallyMovable,
+};
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Uniform},
+    Rng,
+};
+
+/// A point element carrying its insertion index, so results can be compared with a brute force search
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexableUsize {
+    pub point: Point,
+    pub val: usize,
+}
+
+impl SpatiallyIndexable for IndexableUsize {
+    fn x(&self) -> CoordinateType {
+        self.point.x()
+    }
+
+    fn y(&self) -> CoordinateType {
+        self.point.y()
+    }
+}
+
+impl SpatiallyMovable for IndexableUsize {
+    fn set_position(&mut self, point: &Point) {
+        self.point = point.clone();
+    }
+}
+
+impl MappableItem for IndexableUsize {
+    const SIZE: usize = 16;
+
+    fn write_bytes(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.point.x.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.point.y.to_le_bytes());
+        self.val.write_bytes(&mut buf[8..]);
+    }
+
+    fn read_bytes(buf: &[u8]) -> Self {
+        IndexableUsize {
+            point: Point {
+                x: f32::read_bytes(&buf[..4]),
+                y: f32::read_bytes(&buf[4..8]),
+            },
+            val: usize::read_bytes(&buf[8..]),
+        }
+    }
+}
+
+/// Returns a point uniformly distributed over the lon/lat range
+pub fn random_point<C>(rng: &mut impl Rng) -> Point<C>
+where
+    C: Coordinate + SampleUniform + From<i16>,
+{
+    let x_dist = Uniform::new_inclusive(C::from(-180), C::from(180));
+    let y_dist = Uniform::new_inclusive(C::from(-90), C::from(90));
+    Point {
+        x: x_dist.sample(rng),
+        y: y_dist.sample(rng),
+    }
+}
+
+/// Returns n points uniformly distributed over the lon/lat range
+pub fn random_points<C>(n: usize) -> Vec<Point<C>>
+where
+    C: Coordinate + SampleUniform + From<i16>,
+{
+    let mut rng = rand::thread_rng();
+    (0..n).map(|_| random_point(&mut rng)).collect()
+}
+
+/// Returns the bbox spanned by two random points
+pub fn random_query_env<C>() -> BBox<C>
+where
+    C: Coordinate + SampleUniform + From<i16>,
+{
+    let mut query_env = BBox::default();
+    for point in random_points(2) {
+        query_env.expand_to_include_point(&point);
+    }
+    query_env
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "arc-swap")]
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use hprtree::{BBox, ConcurrentHPRTree, HPRTreeBuilder, ItemId, Point};
+
+mod common;
+
+use common::{random_points, IndexableUsize};
+
+#[test]
+fn publish_test() {
+    const N: usize = 1_000;
+
+    let mut index = HPRTreeBuilder::new(N).with_node_capacity(4).with_ids();
+    for (val, point) in random_points(N).into_iter().enumerate() {
+        index.insert(IndexableUsize { point, val });
+    }
+    let mut tree = index.build();
+    tree.remove(ItemId(N - 1));
+    let index = ConcurrentHPRTree::from(tree);
+
+    let first = index.snapshot();
+    assert!(!index.publish());
+    let id = index.insert(IndexableUsize {
+        point: Point { x: 500f32, y: 0f32 },
+        val: N,
+    });
+    assert!(id == ItemId(N));
+    assert!(index.pending_len() == 1);
+    assert!(index.snapshot().len() == N - 1);
+
+    assert!(index.publish());
+    assert!(index.pending_len() == 0);
+    let second = index.snapshot();
+    assert!(second.len() == N);
+    assert!(second.node_capacity() == 4);
+    assert!(second.get(ItemId(N - 1)).is_none());
+    assert!(second.get(ItemId(7)).unwrap().val == 7);
+    assert!(second.get(id).unwrap().val == N);
+    assert!(index.count(&BBox::new(499f32, -1f32, 501f32, 1f32)) == 1);
+    // the earlier snapshot is left as it was
+    assert!(first.len() == N - 1);
+    assert!(first.get(id).is_none());
+}
+
+#[test]
+fn from_tree_without_ids_test() {
+    const N: usize = 100;
+
+    let items: Vec<IndexableUsize> = random_points(N)
+        .into_iter()
+        .enumerate()
+        .map(|(val, point)| IndexableUsize { point, val })
+        .collect();
+    let tree = HPRTreeBuilder::from_vec(items).build();
+    assert!(tree.get(ItemId(0)).is_none());
+
+    // the elements of a tree without ids get the ids of their positions
+    let index = ConcurrentHPRTree::from(tree);
+    let snapshot = index.snapshot();
+    let first = snapshot.get(ItemId(0)).unwrap().val;
+    assert!(index.remove(ItemId(0)));
+    let id = index.insert(IndexableUsize {
+        point: Point { x: 500f32, y: 0f32 },
+        val: N,
+    });
+    assert!(id == ItemId(N));
+    assert!(index.publish());
+    let snapshot = index.snapshot();
+    assert!(snapshot.len() == N);
+    assert!(snapshot.get(ItemId(0)).is_none());
+    assert!(snapshot.get(id).unwrap().val == N);
+    assert!(snapshot
+        .query(&BBox::new(-180f32, -90f32, 180f32, 90f32))
+        .iter()
+        .all(|item| item.val != first));
+}
+
+#[test]
+fn concurrent_readers_test() {
+    const BATCHES: usize = 50;
+    const BATCH_SIZE: usize = 100;
+
+    let index = ConcurrentHPRTree::new();
+    let done = AtomicBool::new(false);
+    let world = BBox::new(-180f32, -90f32, 180f32, 90f32);
+
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                let mut last_len = 0;
+                while !done.load(Ordering::Acquire) {
+                    let snapshot = index.snapshot();
+                    // only whole batches are ever visible and every one of their elements can be found
+                    assert!(snapshot.len() % BATCH_SIZE == 0);
+                    assert!(snapshot.len() >= last_len);
+                    assert!(snapshot.count(&world) == snapshot.len());
+                    last_len = snapshot.len();
+                }
+            });
+        }
+
+        for batch in 0..BATCHES {
+            for (i, point) in random_points(BATCH_SIZE).into_iter().enumerate() {
+                let val = batch * BATCH_SIZE + i;
+                assert!(index.insert(IndexableUsize { point, val }) == ItemId(val));
+            }
+            assert!(index.publish());
+        }
+        done.store(true, Ordering::Release);
+    });
+
+    let snapshot = index.snapshot();
+    assert!(snapshot.len() == BATCHES * BATCH_SIZE);
+    for val in (0..BATCHES * BATCH_SIZE).step_by(97) {
+        assert!(snapshot.get(ItemId(val)).unwrap().val == val);
+    }
+}
+
+#[test]
+fn remove_test() {
+    const N: usize = 1_000;
+
+    let index = ConcurrentHPRTree::new();
+    for point in random_points(N) {
+        index.insert_with(|id| IndexableUsize { point, val: id.0 });
+    }
+    assert!(index.publish());
+
+    // published and still pending elements can be removed alike
+    let pending = index.insert(IndexableUsize {
+        point: Point { x: 0f32, y: 0f32 },
+        val: N,
+    });
+    for id in (0..N).step_by(10) {
+        assert!(index.remove(ItemId(id)));
+    }
+    assert!(index.remove(pending));
+    assert!(index.remove(ItemId(0)));
+    assert!(!index.remove(ItemId(N + 1)));
+    assert!(index.pending_removals() == N / 10 + 2);
+    assert!(index.snapshot().len() == N);
+
+    assert!(index.publish());
+    assert!(index.pending_removals() == 0);
+    let snapshot = index.snapshot();
+    assert!(snapshot.len() == N - N / 10);
+    assert!(snapshot.get(ItemId(0)).is_none());
+    assert!(snapshot.get(pending).is_none());
+    assert!(snapshot.get(ItemId(N - 1)).unwrap().val == N - 1);
+
+    // only removals make for a publish as well
+    assert!(index.remove(ItemId(N - 1)));
+    assert!(index.publish());
+    assert!(index.snapshot().get(ItemId(N - 1)).is_none());
+    assert!(
+        index.insert(IndexableUsize {
+            point: Point { x: 0f32, y: 0f32 },
+            val: N + 1,
+        }) == ItemId(N + 1)
+    );
+}
@@ -0,0 +1,161 @@
+#![cfg(feature = "geojson")]
+
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+use hprtree::{
+    to_feature_collection, BBox, GeoJsonError, GeoJsonFeature, HPRTreeBuilder,
+    HPRTreeWrappingBuilder, Point,
+};
+
+mod common;
+
+use common::random_points;
+
+fn feature(value: Value, val: usize) -> Feature {
+    let mut properties = JsonObject::new();
+    properties.insert("val".to_string(), val.into());
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+fn val(feature: &GeoJsonFeature<f64>) -> usize {
+    feature.properties().unwrap()["val"].as_u64().unwrap() as usize
+}
+
+#[test]
+fn points_test() {
+    const N: usize = 1_000;
+
+    let collection: FeatureCollection = random_points(N)
+        .iter()
+        .enumerate()
+        .map(|(val, point)| feature(Value::Point(vec![point.x, point.y]), val))
+        .collect();
+    // round trip through the text the frontend sends, which may change the last digit of the coordinates
+    let collection: FeatureCollection = collection.to_string().parse().unwrap();
+    let points: Vec<Point<f64>> = collection
+        .features
+        .iter()
+        .map(|feature| match &feature.geometry.as_ref().unwrap().value {
+            Value::Point(position) => Point {
+                x: position[0],
+                y: position[1],
+            },
+            _ => panic!("expected a point"),
+        })
+        .collect();
+
+    let by_bbox = HPRTreeBuilder::<GeoJsonFeature<f64>, f64>::from_geojson(collection.clone())
+        .unwrap()
+        .build();
+    let by_point = HPRTreeWrappingBuilder::<GeoJsonFeature<f64>, f64>::from_geojson(collection)
+        .unwrap()
+        .build();
+    assert!(by_bbox.len() == N);
+    assert!(by_point.len() == N);
+
+    let query_env = BBox::new(-50f64, -20f64, 70f64, 45f64);
+    let mut expected: Vec<usize> = (0..N)
+        .filter(|val| query_env.contains(&points[*val]))
+        .collect();
+    expected.sort();
+    for result in [by_bbox.query(&query_env), by_point.query(&query_env)] {
+        let mut result: Vec<usize> = result.iter().map(val).collect();
+        result.sort();
+        assert!(result == expected);
+    }
+
+    // the results are written back with their geometry and properties
+    let written = to_feature_collection(&by_point.query(&query_env));
+    assert!(written.features.len() == expected.len());
+    for feature in &written.features {
+        let val = feature.properties.as_ref().unwrap()["val"]
+            .as_u64()
+            .unwrap() as usize;
+        let Some(Value::Point(position)) = feature.geometry.as_ref().map(|g| &g.value) else {
+            panic!("expected a point");
+        };
+        assert!(position[..] == [points[val].x, points[val].y]);
+    }
+}
+
+#[test]
+fn extents_test() {
+    let collection: FeatureCollection = [
+        feature(Value::LineString(vec![vec![0.0, 0.0], vec![10.0, 5.0]]), 0),
+        feature(
+            Value::Polygon(vec![vec![
+                vec![20.0, 20.0],
+                vec![30.0, 20.0],
+                vec![30.0, 30.0],
+                vec![20.0, 20.0],
+            ]]),
+            1,
+        ),
+        feature(
+            Value::GeometryCollection(vec![
+                Geometry::new(Value::Point(vec![-5.0, -5.0, 100.0])),
+                Geometry::new(Value::MultiPoint(vec![vec![-1.0, -2.0]])),
+            ]),
+            2,
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let index = HPRTreeBuilder::<GeoJsonFeature<f64>, f64>::from_geojson(collection.clone())
+        .unwrap()
+        .build();
+    let found = |minx, miny, maxx, maxy| -> Vec<usize> {
+        let mut result: Vec<usize> = index
+            .query(&BBox::new(minx, miny, maxx, maxy))
+            .iter()
+            .map(val)
+            .collect();
+        result.sort();
+        result
+    };
+    assert!(found(9.0, 4.0, 11.0, 6.0) == [0]);
+    assert!(found(25.0, 25.0, 26.0, 26.0) == [1]);
+    assert!(found(-3.0, -3.0, -2.0, -2.0) == [2]);
+    assert!(found(-10.0, -10.0, 40.0, 40.0) == [0, 1, 2]);
+
+    // only points can be indexed by position
+    assert!(
+        HPRTreeWrappingBuilder::<GeoJsonFeature<f64>, f64>::from_geojson(collection).err()
+            == Some(GeoJsonError::NotAPoint {
+                feature: 0,
+                geometry: "LineString"
+            })
+    );
+
+    let invalid: [(Feature, GeoJsonError); 3] = [
+        (
+            Feature {
+                geometry: None,
+                ..feature(Value::Point(vec![0.0, 0.0]), 0)
+            },
+            GeoJsonError::MissingGeometry(1),
+        ),
+        (
+            feature(Value::MultiPoint(Vec::new()), 0),
+            GeoJsonError::EmptyGeometry(1),
+        ),
+        (
+            feature(Value::Point(vec![f64::NAN, 0.0]), 0),
+            GeoJsonError::InvalidPosition(1),
+        ),
+    ];
+    for (invalid_feature, error) in invalid {
+        let collection: FeatureCollection =
+            [feature(Value::Point(vec![0.0, 0.0]), 0), invalid_feature]
+                .into_iter()
+                .collect();
+        let result = HPRTreeBuilder::<GeoJsonFeature<f64>, f64>::from_geojson(collection);
+        assert!(result.err() == Some(error));
+    }
+}
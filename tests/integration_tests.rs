@@ -1,7 +1,16 @@
-#![feature(float_next_up_down)]
-
-use hprtree::{BBox, CoordinateType, HPRTreeBuilder, Point, SpatiallyIndexable};
+use hprtree::{
+    flatgeobuf_index_size, join_intersects, join_intersects_visit, join_within_distance, BBox,
+    CoordinateType, DistanceMetric, DynamicHPRTree, Euclidean, FlatGeobufIndex,
+    FlatGeobufIndexError, Geometry, HPRTree, HPRTreeBuilder, Haversine, ItemId, MappableItem,
+    MappedHPRTree, MappedTreeError, Point, Polygon, SpatialPredicate, SpatiallyIndexable,
+    SpatiallyIndexableExtent, WkbError, WktError,
+};
 use rand::prelude::Distribution;
+use std::ops::ControlFlow;
+
+mod common;
+
+use common::{random_point, random_query_env, IndexableUsize};
 
 #[test]
 fn random_contain_test() {
@@ -139,9 +148,9 @@ fn random_contain_test() {
 
     for i in query_top
         .into_iter()
-        .chain(query_left.into_iter())
-        .chain(query_bottom.into_iter())
-        .chain(query_right.into_iter())
+        .chain(query_left)
+        .chain(query_bottom)
+        .chain(query_right)
     {
         assert!(!i.b);
     }
@@ -195,3 +204,1600 @@ fn hprtree_end_to_end() {
         assert!(i >= 340);
     }
 }
+
+#[test]
+fn nearest_k_test() {
+    const N: usize = 20_000;
+    const K: usize = 25;
+
+    let mut rng = rand::thread_rng();
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    let distance = |a: &Point, b: &Point| {
+        let dx = a.x as f64 - b.x as f64;
+        let dy = a.y as f64 - b.y as f64;
+        dx * dx + dy * dy
+    };
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+
+        let mut expected: Vec<f64> = points.iter().map(|p| distance(p, &center)).collect();
+        expected.sort_by(f64::total_cmp);
+
+        let result = index.nearest_k(&center, K);
+        assert!(result.len() == K);
+        for (found, expected) in result.iter().zip(expected.iter()) {
+            assert!(distance(&points[found.val], &center) == *expected);
+        }
+    }
+
+    assert!(index.nearest_k(&Point { x: 0f32, y: 0f32 }, 0).is_empty());
+    assert!(index.nearest_k(&Point { x: 0f32, y: 0f32 }, N + 10).len() == N);
+}
+
+#[test]
+fn query_within_distance_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+    let radius_dist = rand::distributions::Uniform::from(0f64..40f64);
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    let within = |a: &Point, b: &Point, radius: f64| {
+        let dx = a.x as f64 - b.x as f64;
+        let dy = a.y as f64 - b.y as f64;
+        dx * dx + dy * dy <= radius * radius
+    };
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+        let radius = radius_dist.sample(&mut rng);
+
+        let expected = points.iter().filter(|p| within(p, &center, radius)).count();
+        let result = index.query_within_distance(&center, radius);
+        assert!(result.len() == expected);
+        for elem in result {
+            assert!(within(&points[elem.val], &center, radius));
+        }
+    }
+
+    assert!(index
+        .query_within_distance(&Point { x: 0f32, y: 0f32 }, -1f64)
+        .is_empty());
+}
+
+#[test]
+fn haversine_test() {
+    const N: usize = 50_000;
+    const K: usize = 25;
+
+    let metric = Haversine::default();
+
+    // roughly the distance between paris and london
+    let paris = Point {
+        x: 2.3522f32,
+        y: 48.8566f32,
+    };
+    let london = Point {
+        x: -0.1276f32,
+        y: 51.5072f32,
+    };
+    assert!((metric.distance(&paris, &london) - 343_500f64).abs() < 1_000f64);
+
+    let mut rng = rand::thread_rng();
+    let radius_dist = rand::distributions::Uniform::from(0f64..2_000_000f64);
+
+    // the lower bound for a bbox must never exceed the distance to any point within it
+    for _ in 0..1000 {
+        let bbox: BBox = random_query_env();
+        let point = random_point(&mut rng);
+        let bound = metric.min_distance_to_bbox(&point, &bbox);
+        for _ in 0..100 {
+            let inside = Point {
+                x: rand::distributions::Uniform::from(bbox.minx..=bbox.maxx).sample(&mut rng),
+                y: rand::distributions::Uniform::from(bbox.miny..=bbox.maxy).sample(&mut rng),
+            };
+            assert!(bound <= metric.distance(&point, &inside) + 1e-6);
+        }
+    }
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+        let radius = radius_dist.sample(&mut rng);
+
+        let expected = points
+            .iter()
+            .filter(|p| metric.distance(p, &center) <= radius)
+            .count();
+        let result = index.query_within_distance_with_metric(&center, radius, &metric);
+        assert!(result.len() == expected);
+
+        let mut expected: Vec<f64> = points.iter().map(|p| metric.distance(p, &center)).collect();
+        expected.sort_by(f64::total_cmp);
+
+        let result = index.nearest_k_with_metric(&center, K, &metric);
+        assert!(result.len() == K);
+        for (found, expected) in result.iter().zip(expected.iter()) {
+            assert!(metric.distance(&points[found.val], &center) == *expected);
+        }
+    }
+}
+
+#[test]
+fn query_iter_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    for _ in 0..50 {
+        let query_env = random_query_env();
+
+        let expected: Vec<usize> = index.query(&query_env).iter().map(|i| i.val).collect();
+        let result: Vec<usize> = index.query_iter(&query_env).map(|i| i.val).collect();
+        assert!(result == expected);
+
+        let first = index.query_iter(&query_env).next().map(|i| i.val);
+        assert!(first == expected.first().copied());
+    }
+
+    let outside = BBox {
+        minx: 200f32,
+        miny: 100f32,
+        maxx: 300f32,
+        maxy: 200f32,
+    };
+    assert!(index.query_iter(&outside).next().is_none());
+}
+
+#[test]
+fn non_clone_test() {
+    // deliberately neither Clone nor Copy
+    struct IndexableHandle {
+        pub point: Point,
+        pub handle: std::sync::Mutex<usize>,
+    }
+
+    impl SpatiallyIndexable for IndexableHandle {
+        fn x(&self) -> CoordinateType {
+            self.point.x()
+        }
+
+        fn y(&self) -> CoordinateType {
+            self.point.y()
+        }
+    }
+
+    let mut index = HPRTreeBuilder::new(1000);
+    for i in 0..1000 {
+        index.insert(IndexableHandle {
+            point: Point {
+                x: (i % 100) as f32,
+                y: (i / 100) as f32,
+            },
+            handle: std::sync::Mutex::new(i),
+        });
+    }
+    let index = index.build();
+    assert!(index.len() == 1000);
+
+    let query_env = BBox {
+        minx: 10f32,
+        miny: 2f32,
+        maxx: 19f32,
+        maxy: 3f32,
+    };
+    let mut found: Vec<usize> = index
+        .query_iter(&query_env)
+        .map(|i| *i.handle.lock().unwrap())
+        .collect();
+    found.sort();
+    assert!(found == (210..220).chain(310..320).collect::<Vec<usize>>());
+}
+
+#[test]
+fn query_visit_test() {
+    let mut index = HPRTreeBuilder::new(10_000);
+    for val in 0..10_000 {
+        index.insert(IndexableUsize {
+            point: Point {
+                x: (val % 100) as f32,
+                y: (val / 100) as f32,
+            },
+            val,
+        });
+    }
+    let index = index.build();
+
+    let query_env = BBox {
+        minx: 10f32,
+        miny: 10f32,
+        maxx: 29f32,
+        maxy: 29f32,
+    };
+
+    let mut visited = Vec::new();
+    let flow = index.query_visit(&query_env, |item| {
+        visited.push(item.val);
+        ControlFlow::Continue(())
+    });
+    assert!(flow.is_continue());
+    let mut expected: Vec<usize> = index.query(&query_env).iter().map(|i| i.val).collect();
+    expected.sort();
+    visited.sort();
+    assert!(visited == expected);
+
+    let mut calls = 0;
+    let flow = index.query_visit(&query_env, |item| {
+        calls += 1;
+        if item.val % 100 == 15 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert!(flow.is_break());
+    assert!(calls < expected.len());
+
+    let outside = BBox {
+        minx: 200f32,
+        miny: 200f32,
+        maxx: 300f32,
+        maxy: 300f32,
+    };
+    assert!(index
+        .query_visit(&outside, |_| ControlFlow::Break(()))
+        .is_continue());
+}
+
+#[test]
+fn count_and_any_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    for _ in 0..100 {
+        let query_env = random_query_env();
+
+        let expected = index.query(&query_env).len();
+        assert!(index.count(&query_env) == expected);
+        assert!(index.any(&query_env) == (expected != 0));
+    }
+
+    assert!(index.count(&index.extent()) == N);
+    let outside = BBox {
+        minx: 200f32,
+        miny: 100f32,
+        maxx: 300f32,
+        maxy: 200f32,
+    };
+    assert!(index.count(&outside) == 0);
+    assert!(!index.any(&outside));
+}
+
+#[test]
+fn extent_test() {
+    #[derive(Clone)]
+    struct Footprint {
+        pub bbox: BBox,
+        pub val: usize,
+    }
+
+    impl SpatiallyIndexableExtent for Footprint {
+        fn bbox(&self) -> BBox {
+            self.bbox.clone()
+        }
+    }
+
+    const N: usize = 20_000;
+
+    let mut rng = rand::thread_rng();
+    let size_dist = rand::distributions::Uniform::from(0f32..5f32);
+
+    let mut footprints = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let Point { x: minx, y: miny } = random_point(&mut rng);
+        let bbox = BBox::new(
+            minx,
+            miny,
+            minx + size_dist.sample(&mut rng),
+            miny + size_dist.sample(&mut rng),
+        );
+        footprints.push(bbox.clone());
+        index.insert(Footprint { bbox, val });
+    }
+    let index = index.build();
+
+    for _ in 0..50 {
+        let query_env = random_query_env();
+
+        type Check = fn(&BBox, &BBox) -> bool;
+        let predicates: [(SpatialPredicate, Check); 3] = [
+            (SpatialPredicate::Intersects, |env, bbox| {
+                env.intersects(bbox)
+            }),
+            (SpatialPredicate::Contains, |env, bbox| {
+                bbox.contains_bbox(env)
+            }),
+            (SpatialPredicate::Within, |env, bbox| {
+                env.contains_bbox(bbox)
+            }),
+        ];
+        for (predicate, check) in predicates {
+            let expected = footprints.iter().filter(|b| check(&query_env, b)).count();
+            let result = index.query_with_predicate(&query_env, predicate);
+            assert!(result.len() == expected);
+            for elem in result {
+                assert!(check(&query_env, &footprints[elem.val]));
+            }
+        }
+
+        assert!(index.query(&query_env).len() == index.count(&query_env));
+
+        // small envelopes are the interesting case for elements containing the envelope
+        let point = random_point(&mut rng);
+        let tiny_env = BBox::new(point.x, point.y, point.x, point.y);
+        let expected = footprints
+            .iter()
+            .filter(|b| b.contains_bbox(&tiny_env))
+            .count();
+        assert!(
+            index
+                .query_with_predicate(&tiny_env, SpatialPredicate::Contains)
+                .len()
+                == expected
+        );
+
+        let mut distances: Vec<f64> = footprints
+            .iter()
+            .map(|b| Euclidean.min_distance_to_bbox(&point, b))
+            .collect();
+        distances.sort_by(f64::total_cmp);
+        let nearest = index.nearest_k(&point, 10);
+        for (found, expected) in nearest.iter().zip(distances.iter()) {
+            assert!(Euclidean.min_distance_to_bbox(&point, &footprints[found.val]) == *expected);
+        }
+    }
+
+    // every element intersects the extent of the tree and lies within it
+    assert!(index.count(&index.extent()) == N);
+    assert!(
+        index
+            .query_with_predicate(&index.extent(), SpatialPredicate::Within)
+            .len()
+            == N
+    );
+}
+
+#[test]
+fn query_polygon_test() {
+    const N: usize = 100_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    // a concave star with a square hole in the middle
+    let star: Vec<Point> = (0..10)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::PI / 5f32;
+            let radius = if i % 2 == 0 { 80f32 } else { 30f32 };
+            Point {
+                x: 10f32 + radius * angle.cos(),
+                y: 5f32 + radius * angle.sin(),
+            }
+        })
+        .collect();
+    let hole = vec![
+        Point { x: 0f32, y: -5f32 },
+        Point { x: 20f32, y: -5f32 },
+        Point { x: 20f32, y: 15f32 },
+        Point { x: 0f32, y: 15f32 },
+    ];
+    let polygon = Polygon::with_holes(star.clone(), vec![hole]);
+
+    let mut expected: Vec<usize> = (0..N).filter(|i| polygon.contains(&points[*i])).collect();
+    let mut result: Vec<usize> = index
+        .query_polygon_with_holes(&polygon)
+        .iter()
+        .map(|i| i.val)
+        .collect();
+    result.sort();
+    assert!(!expected.is_empty());
+    assert!(result == expected);
+
+    let mut visited = 0;
+    assert!(index
+        .query_visit_polygon(&polygon, |_| {
+            visited += 1;
+            ControlFlow::Continue(())
+        })
+        .is_continue());
+    assert!(visited == expected.len());
+
+    // without the hole the points within it are found as well
+    expected = (0..N)
+        .filter(|i| Polygon::new(star.clone()).contains(&points[*i]))
+        .collect();
+    let mut result: Vec<usize> = index.query_polygon(&star).iter().map(|i| i.val).collect();
+    result.sort();
+    assert!(result == expected);
+}
+
+#[test]
+fn coordinate_type_test() {
+    #[derive(Clone)]
+    struct IndexableF64 {
+        pub point: Point<f64>,
+        pub val: usize,
+    }
+
+    impl SpatiallyIndexable<f64> for IndexableF64 {
+        fn x(&self) -> f64 {
+            self.point.x()
+        }
+
+        fn y(&self) -> f64 {
+            self.point.y()
+        }
+    }
+
+    // offsets this small are lost when the coordinates are stored as f32
+    const ORIGIN: f64 = 1_000_000_000f64;
+    let mut index = HPRTreeBuilder::new(1000);
+    for val in 0..1000 {
+        let point = Point {
+            x: ORIGIN + (val % 40) as f64 * 0.01,
+            y: ORIGIN + (val / 40) as f64 * 0.01,
+        };
+        index.insert(IndexableF64 { point, val });
+    }
+    let index = index.build();
+
+    let result = index.query(&BBox::new(ORIGIN, ORIGIN, ORIGIN + 0.095, ORIGIN + 0.045));
+    assert!(result.len() == 50);
+    for i in result {
+        assert!(i.val % 40 < 10 && i.val / 40 < 5);
+    }
+    let nearest = index.nearest_k(
+        &Point {
+            x: ORIGIN,
+            y: ORIGIN,
+        },
+        1,
+    );
+    assert!(nearest.len() == 1 && nearest[0].val == 0);
+
+    #[derive(Clone)]
+    struct IndexableI64 {
+        pub point: Point<i64>,
+        pub val: i64,
+    }
+
+    impl SpatiallyIndexable<i64> for IndexableI64 {
+        fn x(&self) -> i64 {
+            self.point.x()
+        }
+
+        fn y(&self) -> i64 {
+            self.point.y()
+        }
+    }
+
+    let mut index = HPRTreeBuilder::new(10_000);
+    for val in 0..10_000i64 {
+        let point = Point {
+            x: (val % 100 - 50) * 1_000_000_000,
+            y: (val / 100 - 50) * 1_000_000_000,
+        };
+        index.insert(IndexableI64 { point, val });
+    }
+    let index = index.build();
+
+    let query_env = BBox::new(-10_000_000_000, 0, 9_999_999_999, 4_000_000_000);
+    let result = index.query(&query_env);
+    assert!(result.len() == 20 * 5);
+    for i in &result {
+        assert!(query_env.contains(&i.point));
+    }
+    assert!(index.count(&query_env) == result.len());
+    let nearest = index.nearest_k(
+        &Point {
+            x: 49_000_000_000,
+            y: 49_000_000_000,
+        },
+        1,
+    );
+    assert!(nearest.len() == 1 && nearest[0].val == 9_999);
+    let extent = index.extent();
+    assert!(extent.minx == -50_000_000_000 && extent.miny == -50_000_000_000);
+    assert!(extent.maxx == 49_000_000_000 && extent.maxy == 49_000_000_000);
+}
+
+#[test]
+fn node_capacity_and_hilbert_level_test() {
+    const N: usize = 5_000;
+
+    let mut rng = rand::thread_rng();
+    let points: Vec<Point> = (0..N).map(|_| random_point(&mut rng)).collect();
+
+    for (node_capacity, hilbert_level) in [(2, 1), (3, 8), (16, 12), (64, 16), (N, 16), (N * 2, 16)]
+    {
+        let mut index = HPRTreeBuilder::new(N)
+            .with_node_capacity(node_capacity)
+            .with_hilbert_level(hilbert_level);
+        for (val, point) in points.iter().enumerate() {
+            index.insert(IndexableUsize {
+                point: point.clone(),
+                val,
+            });
+        }
+        let index = index.build();
+        assert!(index.node_capacity() == node_capacity);
+
+        for _ in 0..20 {
+            let query_env = random_query_env();
+            let expected = points.iter().filter(|p| query_env.contains(p)).count();
+            let result = index.query(&query_env);
+            assert!(result.len() == expected);
+            assert!(index.count(&query_env) == expected);
+            assert!(index.query_iter(&query_env).count() == expected);
+            for i in result {
+                assert!(query_env.contains(&points[i.val]));
+            }
+        }
+
+        let point = Point { x: 0f32, y: 0f32 };
+        let mut distances: Vec<f64> = points
+            .iter()
+            .map(|p| Euclidean.distance(&point, p))
+            .collect();
+        distances.sort_by(f64::total_cmp);
+        let nearest = index.nearest_k(&point, 10);
+        for (i, elem) in nearest.iter().enumerate() {
+            assert!(Euclidean.distance(&point, &elem.point) == distances[i]);
+        }
+    }
+}
+
+#[test]
+fn node_overlap_test() {
+    fn overlap_area(a: &BBox, b: &BBox) -> f64 {
+        let width = (a.maxx.min(b.maxx) - a.minx.max(b.minx)).max(0f32) as f64;
+        let height = (a.maxy.min(b.maxy) - a.miny.max(b.miny)).max(0f32) as f64;
+        width * height
+    }
+
+    const N: usize = 20_000;
+
+    let mut rng = rand::thread_rng();
+    // lat/lon data, the axes span different ranges
+
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+    let extent_area = index.extent().area();
+
+    for layer in 0..index.depth() {
+        let bounds = index.layer_bounds(layer);
+
+        // the nodes of well packed uniform data cover the extent about once and barely overlap, a broken hilbert order covers it many times over
+        // the few large nodes of the upper layers occasionally cover it about 1.5 times, so the bound leaves some room above that
+        let coverage = bounds.iter().map(|b| b.area()).sum::<f64>() / extent_area;
+        assert!(
+            coverage < 2.0,
+            "layer {layer} covers the extent {coverage} times"
+        );
+
+        let mut overlap = 0f64;
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                overlap += overlap_area(a, b);
+            }
+        }
+        let overlap = overlap / extent_area;
+        assert!(overlap < 1.0, "layer {layer} has an overlap of {overlap}");
+    }
+}
+
+#[test]
+fn mapped_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        index.insert(IndexableUsize { point, val });
+    }
+    let index = index.build();
+
+    let path = std::env::temp_dir().join(format!("hprtree_mapped_test_{}", std::process::id()));
+    index
+        .write_mapped(&mut std::fs::File::create(&path).unwrap())
+        .unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mapped = MappedHPRTree::<IndexableUsize, f32>::from_bytes(&bytes).unwrap();
+    assert!(mapped.len() == N);
+    assert!(mapped.item_size() == IndexableUsize::SIZE);
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let expected: Vec<usize> = index.query(&query_env).iter().map(|i| i.val).collect();
+        let result = mapped.query(&query_env);
+        assert!(result.len() == expected.len());
+        for (item, val) in result.iter().zip(expected) {
+            let elem: IndexableUsize = item.get();
+            assert!(elem.val == val);
+            assert!(query_env.contains(&elem.point));
+            assert!(item.bbox().contains(&elem.point));
+        }
+
+        let point = random_point(&mut rng);
+        let expected: Vec<usize> = index.nearest_k(&point, 10).iter().map(|i| i.val).collect();
+        let result: Vec<usize> = mapped
+            .nearest_k(&point, 10)
+            .iter()
+            .map(|item| item.get().val)
+            .collect();
+        assert!(result == expected);
+        let expected: Vec<usize> = index
+            .query_within_distance(&point, 20f64)
+            .iter()
+            .map(|i| i.val)
+            .collect();
+        let result: Vec<usize> = mapped
+            .query_within_distance(&point, 20f64)
+            .iter()
+            .map(|item| item.get().val)
+            .collect();
+        assert!(result == expected);
+    }
+    assert!(mapped.nearest_k(&Point { x: 0f32, y: 0f32 }, 0).is_empty());
+    assert!(mapped.nearest_k(&Point { x: 0f32, y: 0f32 }, N + 1).len() == N);
+
+    assert!(mapped.depth() == index.depth());
+    for layer in 0..index.depth() {
+        let bounds = mapped.layer_bounds(layer);
+        assert!(bounds.len() == index.layer_bounds(layer).len());
+        for (mapped_bbox, bbox) in bounds.zip(index.layer_bounds(layer)) {
+            assert!(mapped_bbox.minx == bbox.minx && mapped_bbox.miny == bbox.miny);
+            assert!(mapped_bbox.maxx == bbox.maxx && mapped_bbox.maxy == bbox.maxy);
+        }
+    }
+
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f64>::from_bytes(&bytes),
+        Err(MappedTreeError::CoordinateType { .. })
+    ));
+    // a file written with elements of another size is rejected instead of failing on the first read
+    assert!(
+        MappedHPRTree::<usize, f32>::from_bytes(&bytes).err()
+            == Some(MappedTreeError::ItemSize {
+                expected: 8,
+                found: IndexableUsize::SIZE,
+            })
+    );
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(MappedTreeError::Length { .. })
+    ));
+    let mut corrupted = bytes.clone();
+    corrupted[0] = b'X';
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&corrupted),
+        Err(MappedTreeError::Magic)
+    ));
+    let mut corrupted = bytes.clone();
+    corrupted[8] = 2;
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&corrupted),
+        Err(MappedTreeError::Version(2))
+    ));
+    let mut corrupted = bytes.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert!(mapped.verify_checksum().is_ok());
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&corrupted)
+            .unwrap()
+            .verify_checksum(),
+        Err(MappedTreeError::Checksum { .. })
+    ));
+
+    // a header claiming more elements than could possibly fit is rejected before anything is computed from it
+    let mut corrupted = bytes.clone();
+    corrupted[16..20].copy_from_slice(&2u32.to_le_bytes());
+    corrupted[24..32].copy_from_slice(&(1u64 << 62).to_le_bytes());
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&corrupted),
+        Err(MappedTreeError::Structure)
+    ));
+    let mut corrupted = bytes.clone();
+    corrupted[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(
+        MappedHPRTree::<IndexableUsize, f32>::from_bytes(&corrupted),
+        Err(MappedTreeError::Structure)
+    ));
+}
+
+#[test]
+fn flatgeobuf_test() {
+    #[derive(Clone)]
+    struct Footprint {
+        pub bbox: BBox,
+        pub val: usize,
+    }
+
+    impl SpatiallyIndexableExtent for Footprint {
+        fn bbox(&self) -> BBox {
+            self.bbox.clone()
+        }
+    }
+
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let size_dist = rand::distributions::Uniform::from(0f32..2f32);
+
+    let mut index = HPRTreeBuilder::new(N);
+    for val in 0..N {
+        let Point { x: minx, y: miny } = random_point(&mut rng);
+        let bbox = BBox::new(
+            minx,
+            miny,
+            minx + size_dist.sample(&mut rng),
+            miny + size_dist.sample(&mut rng),
+        );
+        index.insert(Footprint { bbox, val });
+    }
+    let index = index.build();
+
+    // every feature is pretended to take up 100 bytes, written in the order of the tree
+    let mut bytes = Vec::new();
+    let mut order = Vec::with_capacity(N);
+    index
+        .write_flatgeobuf_index(&mut bytes, |item| {
+            order.push(item.val);
+            (order.len() as u64 - 1) * 100
+        })
+        .unwrap();
+    assert!(Some(bytes.len()) == flatgeobuf_index_size(N, 16));
+
+    let fgb = FlatGeobufIndex::new(&bytes, N, 16).unwrap();
+    assert!(fgb.len() == N);
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let expected: Vec<usize> = index.query(&query_env).iter().map(|i| i.val).collect();
+        let result = fgb.search(&BBox::new(
+            query_env.minx as f64,
+            query_env.miny as f64,
+            query_env.maxx as f64,
+            query_env.maxy as f64,
+        ));
+        assert!(result.len() == expected.len());
+        for (found, val) in result.iter().zip(expected) {
+            assert!(found.offset == found.index as u64 * 100);
+            assert!(order[found.index] == val);
+        }
+    }
+
+    assert!(matches!(
+        FlatGeobufIndex::new(&bytes[..bytes.len() - 1], N, 16),
+        Err(FlatGeobufIndexError::Length { .. })
+    ));
+    // a number of features from a corrupted header whose index size does not even fit in a usize
+    assert!(flatgeobuf_index_size(1 << 60, 16).is_none());
+    assert!(matches!(
+        FlatGeobufIndex::new(&bytes, 1 << 60, 16),
+        Err(FlatGeobufIndexError::Length { .. })
+    ));
+}
+
+#[test]
+fn dynamic_test() {
+    const N: usize = 20_000;
+
+    let mut rng = rand::thread_rng();
+    let mut random_point = || random_point(&mut rng);
+
+    let points: Vec<Point> = (0..N).map(|_| random_point()).collect();
+    let query_envs: Vec<BBox> = (0..20)
+        .map(|_| {
+            let mut query_env = BBox::default();
+            query_env.expand_to_include_point(&random_point());
+            query_env.expand_to_include_point(&random_point());
+            query_env
+        })
+        .collect();
+
+    let mut index = DynamicHPRTree::new()
+        .with_buffer_capacity(64)
+        .with_node_capacity(8);
+    let mut expected = HPRTreeBuilder::new(N);
+    for (val, point) in points.into_iter().enumerate() {
+        let item = IndexableUsize { point, val };
+        index.insert(item.clone());
+        expected.insert(item);
+
+        if val % 5_000 == 4_999 {
+            assert!(index.len() == val + 1);
+            // inserting only packs the buffer, merging is left to maintain
+            assert!(index.run_count() > 8);
+            assert!(index.maintain());
+            assert!(!index.maintain());
+            // the run sizes grow geometrically, so there are only logarithmically many of them
+            assert!(index.run_count() <= 8);
+            assert!(index.len() == val + 1);
+        }
+    }
+    let expected = expected.build();
+    assert!(index.extent().minx == expected.extent().minx);
+    assert!(index.extent().maxy == expected.extent().maxy);
+
+    let check = |index: &DynamicHPRTree<IndexableUsize>| {
+        for query_env in &query_envs {
+            let mut result: Vec<usize> = index.query(query_env).iter().map(|i| i.val).collect();
+            result.sort();
+            let mut iter_result: Vec<usize> = index.query_iter(query_env).map(|i| i.val).collect();
+            iter_result.sort();
+            let mut expected: Vec<usize> =
+                expected.query(query_env).iter().map(|i| i.val).collect();
+            expected.sort();
+            assert!(result == expected);
+            assert!(iter_result == expected);
+            assert!(index.count(query_env) == expected.len());
+            assert_eq!(index.any(query_env), !expected.is_empty());
+        }
+    };
+    check(&index);
+
+    index.compact();
+    assert!(index.run_count() == 1);
+    assert!(index.len() == N);
+    check(&index);
+
+    let tree = index.into_tree();
+    assert!(tree.len() == N && tree.node_capacity() == 8);
+
+    let mut index = DynamicHPRTree::from(tree);
+    index.insert(IndexableUsize {
+        point: Point { x: 500f32, y: 0f32 },
+        val: N,
+    });
+    assert!(index.run_count() == 1 && index.len() == N + 1);
+    assert!(index.query(&BBox::new(499f32, -1f32, 501f32, 1f32))[0].val == N);
+
+    // the runs built later keep the options of the tree the index was created from
+    let mut builder = HPRTreeBuilder::new(2).with_hilbert_level(4);
+    builder.insert(Point { x: 0f32, y: 0f32 });
+    let mut index = DynamicHPRTree::from(builder.build());
+    index.insert(Point { x: 1f32, y: 1f32 });
+    let tree = index.into_tree();
+    assert!(tree.len() == 2 && tree.hilbert_level() == 4);
+
+    let empty = DynamicHPRTree::<Point>::new().into_tree();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn dynamic_remove_and_merge_test() {
+    const N: usize = 5_000;
+
+    let mut rng = rand::thread_rng();
+    let items: Vec<IndexableUsize> = (0..N)
+        .map(|val| IndexableUsize {
+            point: random_point(&mut rng),
+            val,
+        })
+        .collect();
+
+    let mut index = DynamicHPRTree::new()
+        .with_buffer_capacity(50)
+        .with_node_capacity(8);
+    for item in items.iter().cloned() {
+        index.insert(item);
+    }
+    index.maintain();
+    assert!(index.len() == N);
+
+    // every tenth element is removed, from the runs and from the buffer alike
+    let bbox = |point: &Point| BBox::new(point.x, point.y, point.x, point.y);
+    for item in items.iter().step_by(10) {
+        assert!(index.remove_matching(&bbox(&item.point), |found| found.val == item.val));
+        assert!(!index.remove_matching(&bbox(&item.point), |found| found.val == item.val));
+    }
+    let remaining: Vec<IndexableUsize> = items
+        .iter()
+        .filter(|item| item.val % 10 != 0)
+        .cloned()
+        .collect();
+    assert!(index.len() == remaining.len());
+    let expected = HPRTreeBuilder::from_vec(remaining).build();
+
+    for _ in 0..20 {
+        let center = random_point(&mut rng);
+        let mut result: Vec<usize> = index
+            .query_within_distance(&center, 20f64)
+            .iter()
+            .map(|item| item.val)
+            .collect();
+        result.sort();
+        let mut expected_vals: Vec<usize> = expected
+            .query_within_distance(&center, 20f64)
+            .iter()
+            .map(|item| item.val)
+            .collect();
+        expected_vals.sort();
+        assert!(result == expected_vals);
+
+        // ties may be broken differently, so the distances are compared
+        let distances = |items: Vec<IndexableUsize>| -> Vec<f64> {
+            items
+                .iter()
+                .map(|item| Euclidean.distance(&center, &item.point))
+                .collect()
+        };
+        let nearest = distances(index.nearest_k(&center, 10));
+        assert!(nearest.len() == 10);
+        assert!(nearest == distances(expected.nearest_k(&center, 10)));
+    }
+
+    // a merge is built next to the index and swapped in, unless the merged runs changed in the meantime
+    for item in items.iter().take(500) {
+        index.insert(item.clone());
+    }
+    let run_count = index.run_count();
+    let pending = index.start_merge().unwrap();
+    assert!(pending.len() >= 100);
+    let merged = pending.build();
+    assert!(index.finish_merge(merged));
+    assert!(index.run_count() < run_count);
+
+    // elements removed while a merge is built stay removed once it is swapped in
+    while index.maintain() {}
+    let ids: Vec<ItemId> = items
+        .iter()
+        .skip(500)
+        .take(100)
+        .map(|item| index.insert(item.clone()))
+        .collect();
+    let pending = index.start_merge().unwrap();
+    assert!(index.remove(ids[0]));
+    let merged = pending.build();
+    assert!(index.finish_merge(merged));
+    assert!(index.get(ids[0]).is_none());
+    assert!(index.get(ids[1]).unwrap().val == 501);
+    assert!(index.len() == N - N / 10 + 600 - 1);
+
+    // a merge is only dropped if the merged runs have been rebuilt in the meantime
+    for item in items.iter().skip(600).take(100) {
+        index.insert(item.clone());
+    }
+    let stale = index.start_merge().unwrap().build();
+    index.compact();
+    assert!(index.run_count() == 1);
+    assert!(!index.finish_merge(stale));
+    assert!(index.run_count() == 1);
+    assert!(index.len() == N - N / 10 + 700 - 1);
+}
+
+#[test]
+fn dynamic_ids_test() {
+    const N: usize = 1_000;
+
+    let mut rng = rand::thread_rng();
+    let mut index = DynamicHPRTree::new().with_buffer_capacity(16);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        assert!(index.insert(IndexableUsize { point, val }) == ItemId(val));
+    }
+    assert!(index.get(ItemId(N)).is_none());
+
+    // removing from the runs and from the buffer, the ids stay the same through merges and compaction
+    for id in (0..N).step_by(3) {
+        assert!(index.remove(ItemId(id)));
+        assert!(!index.remove(ItemId(id)));
+    }
+    while index.maintain() {}
+    let check = |index: &DynamicHPRTree<IndexableUsize>| {
+        for id in 0..N {
+            match index.get(ItemId(id)) {
+                Some(item) => assert!(id % 3 != 0 && item.val == id),
+                None => assert!(id % 3 == 0),
+            }
+        }
+    };
+    check(&index);
+    index.compact();
+    check(&index);
+
+    // the static tree keeps the ids and does not hand out those of removed elements again
+    let tree = index.into_tree();
+    assert!(tree.get(ItemId(1)).unwrap().val == 1);
+    assert!(tree.get(ItemId(0)).is_none());
+    let mut index = DynamicHPRTree::from(tree);
+    assert!(index.get(ItemId(N - 2)).unwrap().val == N - 2);
+    assert!(index.next_id() == ItemId(N));
+    let point = random_point(&mut rng);
+    assert!(index.insert(IndexableUsize { point, val: N }) == ItemId(N));
+
+    // a tree without ids gives its elements the ids of their positions
+    let tree: HPRTree<Point> = (0..10)
+        .map(|x| Point {
+            x: x as f32,
+            y: 0f32,
+        })
+        .collect();
+    let mut index = DynamicHPRTree::from(tree);
+    assert!(index.get(ItemId(9)).is_some());
+    assert!(index.insert(Point { x: 10f32, y: 0f32 }) == ItemId(10));
+}
+
+#[test]
+fn remove_and_update_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let mut random_point = || random_point(&mut rng);
+
+    // the expected state, the point of each id or None once it is removed
+    let mut model: Vec<Option<Point>> = Vec::with_capacity(N);
+    let mut index = HPRTreeBuilder::new(N).with_ids();
+    for val in 0..N {
+        let point = random_point();
+        model.push(Some(point.clone()));
+        assert!(index.insert(IndexableUsize { point, val }) == ItemId(val));
+    }
+    let mut index = index.build();
+    let query_envs: Vec<BBox> = (0..20)
+        .map(|_| {
+            let mut query_env = BBox::default();
+            query_env.expand_to_include_point(&random_point());
+            query_env.expand_to_include_point(&random_point());
+            query_env
+        })
+        .collect();
+
+    let check = |index: &hprtree::HPRTree<IndexableUsize>, model: &[Option<Point>]| {
+        assert!(index.len() == model.iter().flatten().count());
+        for (id, point) in model.iter().enumerate() {
+            match point {
+                Some(point) => {
+                    let item = index.get(ItemId(id)).unwrap();
+                    assert!(item.val == id && item.point.x == point.x && item.point.y == point.y);
+                }
+                None => assert!(index.get(ItemId(id)).is_none()),
+            }
+        }
+        for query_env in &query_envs {
+            let mut expected: Vec<usize> = model
+                .iter()
+                .enumerate()
+                .filter(|(_, point)| point.as_ref().is_some_and(|p| query_env.contains(p)))
+                .map(|(id, _)| id)
+                .collect();
+            let mut result: Vec<usize> = index.query(query_env).iter().map(|i| i.val).collect();
+            result.sort();
+            let mut iter_result: Vec<usize> = index.query_iter(query_env).map(|i| i.val).collect();
+            iter_result.sort();
+            expected.sort();
+            assert!(result == expected);
+            assert!(iter_result == expected);
+            assert!(index.count(query_env) == expected.len());
+        }
+        let center = Point { x: 0f32, y: 0f32 };
+        for item in index.nearest_k(&center, 50) {
+            assert!(model[item.val].is_some());
+        }
+    };
+
+    // removed elements are only marked, a fifth of them stays below the compaction threshold
+    for id in (0..N).step_by(5) {
+        assert!(index.remove(ItemId(id)));
+        model[id] = None;
+    }
+    assert!(!index.remove(ItemId(0)));
+    assert!(!index.remove(ItemId(N)));
+    assert!(!index.needs_compaction());
+    check(&index, &model);
+    assert!(index
+        .write_flatgeobuf_index(&mut Vec::new(), |item| item.val as u64)
+        .is_err());
+
+    for id in (1..N).step_by(50) {
+        let point = random_point();
+        let previous = index
+            .update(
+                ItemId(id),
+                IndexableUsize {
+                    point: point.clone(),
+                    val: id,
+                },
+            )
+            .unwrap();
+        assert!(previous.val == id);
+        model[id] = Some(point);
+    }
+    assert!(index
+        .update(
+            ItemId(0),
+            IndexableUsize {
+                point: random_point(),
+                val: 0
+            }
+        )
+        .is_none());
+    for id in (3..N).step_by(50) {
+        let point = random_point();
+        assert!(index.update_position(ItemId(id), point.clone()));
+        model[id] = Some(point);
+    }
+    assert!(!index.update_position(ItemId(0), random_point()));
+    // moving an element outside of the extent grows the extent
+    assert!(index.update_position(ItemId(1), Point { x: 500f32, y: 0f32 }));
+    model[1] = Some(Point { x: 500f32, y: 0f32 });
+    assert!(index.extent().maxx == 500f32);
+    check(&index, &model);
+
+    // passing the threshold is only reported, the tree is compacted when asked to and the ids stay the same
+    for id in (2..N).step_by(5) {
+        index.remove(ItemId(id));
+        model[id] = None;
+    }
+    assert!(index.needs_compaction());
+    check(&index, &model);
+
+    index.compact();
+    assert!(!index.needs_compaction());
+    check(&index, &model);
+    assert!(index
+        .write_flatgeobuf_index(&mut Vec::new(), |item| item.val as u64)
+        .is_ok());
+
+    // removing the element with the highest id has to keep it removed through compaction
+    index.remove(ItemId(N - 1));
+    model[N - 1] = None;
+    index.compact();
+    check(&index, &model);
+}
+
+#[test]
+fn from_iter_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let items: Vec<IndexableUsize> = (0..N)
+        .map(|val| IndexableUsize {
+            point: random_point(&mut rng),
+            val,
+        })
+        .collect();
+
+    let mut inserted = HPRTreeBuilder::new(N);
+    for item in items.iter().cloned() {
+        inserted.insert(item);
+    }
+    let inserted = inserted.build();
+
+    let mut extended = HPRTreeBuilder::new(0).with_ids();
+    extended.extend(items.iter().take(N / 2).cloned());
+    extended.extend(items.iter().skip(N / 2).cloned());
+    let trees = [
+        HPRTreeBuilder::from_vec(items.clone()).with_ids().build(),
+        items
+            .iter()
+            .cloned()
+            .collect::<HPRTreeBuilder<IndexableUsize>>()
+            .with_ids()
+            .build(),
+        extended.build(),
+        items.iter().cloned().collect(),
+    ];
+
+    for tree in &trees {
+        assert!(tree.len() == N);
+        assert!(tree.extent().minx == inserted.extent().minx);
+        assert!(tree.extent().miny == inserted.extent().miny);
+        assert!(tree.extent().maxx == inserted.extent().maxx);
+        assert!(tree.extent().maxy == inserted.extent().maxy);
+    }
+    // the ids are assigned in iteration order just like with insert, a tree collected directly keeps none
+    for tree in &trees[..3] {
+        for id in [0, N / 2, N - 1] {
+            assert!(tree.get(ItemId(id)).unwrap().val == id);
+        }
+    }
+    assert!(trees[3].get(ItemId(0)).is_none());
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let expected: Vec<usize> = inserted.query(&query_env).iter().map(|i| i.val).collect();
+        for tree in &trees {
+            let result: Vec<usize> = tree.query(&query_env).iter().map(|i| i.val).collect();
+            assert!(result == expected);
+        }
+    }
+
+    let empty: HPRTree<IndexableUsize> = std::iter::empty().collect();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn join_test() {
+    #[derive(Clone)]
+    struct Footprint {
+        pub bbox: BBox,
+        pub val: usize,
+    }
+
+    impl SpatiallyIndexableExtent for Footprint {
+        fn bbox(&self) -> BBox {
+            self.bbox.clone()
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    let size_dist = rand::distributions::Uniform::from(0f32..3f32);
+    let mut random_footprints = |n: usize, max_size: f32| -> Vec<Footprint> {
+        (0..n)
+            .map(|val| {
+                let Point { x: minx, y: miny } = random_point(&mut rng);
+                let bbox = BBox::new(
+                    minx,
+                    miny,
+                    minx + size_dist.sample(&mut rng) * max_size,
+                    miny + size_dist.sample(&mut rng) * max_size,
+                );
+                Footprint { bbox, val }
+            })
+            .collect()
+    };
+
+    let pings = random_footprints(5_000, 0f32);
+    let pois = random_footprints(1_000, 1f32);
+    let few = random_footprints(10, 1f32);
+
+    let brute_force = |left: &[Footprint], right: &[Footprint], distance: f64| {
+        let mut pairs = Vec::new();
+        for a in left {
+            for b in right {
+                let dx = (b.bbox.minx - a.bbox.maxx)
+                    .max(a.bbox.minx - b.bbox.maxx)
+                    .max(0f32) as f64;
+                let dy = (b.bbox.miny - a.bbox.maxy)
+                    .max(a.bbox.miny - b.bbox.maxy)
+                    .max(0f32) as f64;
+                if dx * dx + dy * dy <= distance * distance {
+                    pairs.push((a.val, b.val));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    };
+    let sorted_vals = |pairs: Vec<(&Footprint, &Footprint)>| {
+        let mut pairs: Vec<(usize, usize)> = pairs.iter().map(|(a, b)| (a.val, b.val)).collect();
+        pairs.sort();
+        pairs
+    };
+
+    let ping_tree: HPRTree<Footprint> = pings.iter().cloned().collect();
+    let poi_tree = HPRTreeBuilder::from_vec(pois.clone())
+        .with_node_capacity(4)
+        .with_ids()
+        .build();
+    let few_tree: HPRTree<Footprint> = few.iter().cloned().collect();
+    assert!(few_tree.depth() == 0);
+
+    for distance in [0f64, 0.5f64, 2f64] {
+        let expected = brute_force(&pings, &pois, distance);
+        let result = sorted_vals(join_within_distance(&ping_tree, &poi_tree, distance));
+        assert!(result == expected);
+        let result = sorted_vals(join_within_distance(&few_tree, &poi_tree, distance));
+        assert!(result == brute_force(&few, &pois, distance));
+    }
+    assert!(join_within_distance(&ping_tree, &poi_tree, -1f64).is_empty());
+
+    let expected = brute_force(&pois, &pings, 0f64);
+    assert!(!expected.is_empty());
+    assert!(sorted_vals(join_intersects(&poi_tree, &ping_tree)) == expected);
+    assert!(sorted_vals(join_intersects(&poi_tree, &few_tree)) == brute_force(&pois, &few, 0f64));
+
+    let mut visited = 0;
+    let flow = join_intersects_visit(&poi_tree, &ping_tree, |_, _| {
+        visited += 1;
+        ControlFlow::Break(())
+    });
+    assert!(flow.is_break() && visited == 1);
+
+    // removed elements are not joined
+    let mut poi_tree = poi_tree;
+    for id in 0..100 {
+        poi_tree.remove(ItemId(id));
+    }
+    let expected: Vec<(usize, usize)> = expected.into_iter().filter(|(a, _)| *a >= 100).collect();
+    assert!(sorted_vals(join_intersects(&poi_tree, &ping_tree)) == expected);
+}
+
+#[test]
+fn wkt_wkb_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let points: Vec<Point<f64>> = (0..N).map(|_| random_point(&mut rng)).collect();
+    let index = HPRTreeBuilder::from_vec(points.clone()).build();
+
+    // a square with a hole, typed like an analyst would
+    let wkt =
+        "polygon (( -50 -40, 50 -40, 50 40, -50 40, -50 -40 ), (-10 -10,10 -10,10 10,-10 10))";
+    let Ok(Geometry::Polygon(polygon)) = Geometry::<f64>::from_wkt(wkt) else {
+        panic!("expected a polygon");
+    };
+    let expected = Polygon::with_holes(
+        vec![
+            Point {
+                x: -50f64,
+                y: -40f64,
+            },
+            Point {
+                x: 50f64,
+                y: -40f64,
+            },
+            Point { x: 50f64, y: 40f64 },
+            Point {
+                x: -50f64,
+                y: 40f64,
+            },
+        ],
+        vec![vec![
+            Point {
+                x: -10f64,
+                y: -10f64,
+            },
+            Point {
+                x: 10f64,
+                y: -10f64,
+            },
+            Point { x: 10f64, y: 10f64 },
+            Point {
+                x: -10f64,
+                y: 10f64,
+            },
+        ]],
+    );
+    let mut result: Vec<(f64, f64)> = index
+        .query_polygon_with_holes(&polygon)
+        .iter()
+        .map(|p| (p.x, p.y))
+        .collect();
+    let mut expected_result: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|p| expected.contains(p))
+        .map(|p| (p.x, p.y))
+        .collect();
+    result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    expected_result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!(!result.is_empty() && result == expected_result);
+
+    // envelopes in the CQL and PostGIS notations
+    for wkt in ["ENVELOPE(-50, 50, 40, -40)", "BOX(-50 -40,50 40)"] {
+        let Ok(Geometry::Envelope(bbox)) = Geometry::<f64>::from_wkt(wkt) else {
+            panic!("expected an envelope");
+        };
+        assert!(
+            bbox.minx == -50f64 && bbox.miny == -40f64 && bbox.maxx == 50f64 && bbox.maxy == 40f64
+        );
+        assert!(index.query(&bbox).len() >= result.len());
+    }
+
+    // results are written back, envelopes as polygons and rings closed
+    let written = Geometry::from(polygon.clone()).to_wkt();
+    assert!(written == "POLYGON((-50.0 -40.0,50.0 -40.0,50.0 40.0,-50.0 40.0,-50.0 -40.0),(-10.0 -10.0,10.0 -10.0,10.0 10.0,-10.0 10.0,-10.0 -10.0))");
+    assert!(
+        Geometry::from(BBox::new(0f64, 1f64, 2f64, 3f64)).to_wkt()
+            == "POLYGON((0.0 1.0,2.0 1.0,2.0 3.0,0.0 3.0,0.0 1.0))"
+    );
+    let multi = "SRID=4326;MULTIPOLYGON Z(((0 0 1,1 0 1,1 1 1,0 0 1)),((5 5 2,6 5 2,6 6 2,5 5 2)))";
+    let multi = Geometry::<f64>::from_wkt(multi).unwrap();
+    let bbox = multi.bbox();
+    assert!(bbox.minx == 0f64 && bbox.miny == 0f64 && bbox.maxx == 6f64 && bbox.maxy == 6f64);
+    for geometry in [
+        Geometry::Point(points[0].clone()),
+        Geometry::Polygon(polygon),
+        multi,
+    ] {
+        let wkt = geometry.to_wkt();
+        assert!(Geometry::<f64>::from_wkt(&wkt).unwrap().to_wkt() == wkt);
+        assert!(
+            Geometry::<f64>::from_wkb(&geometry.to_wkb())
+                .unwrap()
+                .to_wkt()
+                == wkt
+        );
+        assert!(
+            Geometry::<f64>::from_wkb_hex(&geometry.to_wkb_hex())
+                .unwrap()
+                .to_wkt()
+                == wkt
+        );
+    }
+
+    // the polygons of a multipolygon overlap in the middle, the elements within both are found once
+    let Ok(Geometry::MultiPolygon(polygons)) = Geometry::<f64>::from_wkt(
+        "MULTIPOLYGON(((-50 -40,10 -40,10 40,-50 40,-50 -40)),((-10 -40,50 -40,50 40,-10 40,-10 -40)))",
+    ) else {
+        panic!("expected a multipolygon");
+    };
+    let mut result: Vec<(f64, f64)> = index
+        .query_multi_polygon(&polygons)
+        .iter()
+        .map(|p| (p.x, p.y))
+        .collect();
+    let union = BBox::new(-50f64, -40f64, 50f64, 40f64);
+    let mut expected_result: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|p| union.contains(p))
+        .map(|p| (p.x, p.y))
+        .collect();
+    result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    expected_result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!(result == expected_result);
+    let overlapping: usize = polygons
+        .iter()
+        .map(|polygon| index.query_polygon_with_holes(polygon).len())
+        .sum();
+    assert!(overlapping > result.len());
+
+    // an empty multipolygon is read back as it is written and finds nothing
+    let empty = Geometry::<f64>::MultiPolygon(Vec::new());
+    assert!(empty.to_wkt() == "MULTIPOLYGON EMPTY");
+    for read in [
+        Geometry::<f64>::from_wkt("MULTIPOLYGON EMPTY").ok(),
+        Geometry::<f64>::from_wkt("multipolygon z empty").ok(),
+        Geometry::<f64>::from_wkb(&empty.to_wkb()).ok(),
+    ] {
+        let Some(Geometry::MultiPolygon(polygons)) = read else {
+            panic!("expected an empty multipolygon");
+        };
+        assert!(polygons.is_empty() && index.query_multi_polygon(&polygons).is_empty());
+    }
+
+    // EWKB with an SRID as exported by PostGIS, and big endian WKB
+    for hex in [
+        "0101000020E6100000000000000000F03F0000000000000040",
+        "\\x00000000013FF00000000000004000000000000000",
+    ] {
+        let Ok(Geometry::Point(point)) = Geometry::<f32>::from_wkb_hex(hex) else {
+            panic!("expected a point");
+        };
+        assert!(point.x == 1f32 && point.y == 2f32);
+    }
+
+    let invalid_wkt = [
+        ("", WktError::UnexpectedEnd),
+        ("POINT(1 2", WktError::UnexpectedEnd),
+        (
+            "LINESTRING(0 0,1 1)",
+            WktError::UnsupportedType("LINESTRING".to_string()),
+        ),
+        ("POINT(1 x)", WktError::InvalidNumber { position: 8 }),
+        ("POINT(1 inf)", WktError::InvalidNumber { position: 8 }),
+        ("POINT EMPTY", WktError::Empty),
+        ("POLYGON EMPTY", WktError::Empty),
+        ("MULTIPOLYGON(EMPTY)", WktError::Empty),
+        (
+            "MULTIPOLYGON EMPTY 1",
+            WktError::Unexpected {
+                position: 19,
+                expected: "the end of the WKT",
+            },
+        ),
+        ("POLYGON((0 0,1 1))", WktError::TooFewPoints { position: 8 }),
+        (
+            "POINT(1 2) 3",
+            WktError::Unexpected {
+                position: 11,
+                expected: "the end of the WKT",
+            },
+        ),
+        (
+            "ENVELOPE(1 2 3 4)",
+            WktError::Unexpected {
+                position: 11,
+                expected: "','",
+            },
+        ),
+    ];
+    for (wkt, error) in invalid_wkt {
+        assert!(Geometry::<f64>::from_wkt(wkt).err() == Some(error));
+    }
+
+    // every truncation of valid WKB is an error, not a panic
+    let wkb = Geometry::<f64>::from_wkt("MULTIPOLYGON(((0 0,1 0,1 1,0 0)),((5 5,6 5,6 6,5 5)))")
+        .unwrap()
+        .to_wkb();
+    for len in 0..wkb.len() {
+        assert!(Geometry::<f64>::from_wkb(&wkb[..len]).is_err());
+    }
+    let mut trailing = wkb.clone();
+    trailing.push(0);
+    assert!(
+        Geometry::<f64>::from_wkb(&trailing).err()
+            == Some(WkbError::TrailingBytes { offset: wkb.len() })
+    );
+    let mut bad_order = wkb.clone();
+    bad_order[0] = 2;
+    assert!(
+        Geometry::<f64>::from_wkb(&bad_order).err()
+            == Some(WkbError::ByteOrder {
+                offset: 0,
+                found: 2
+            })
+    );
+    let line = "010200000000000000";
+    assert!(
+        Geometry::<f64>::from_wkb_hex(line).err()
+            == Some(WkbError::UnsupportedType {
+                offset: 0,
+                geometry_type: 2
+            })
+    );
+    assert!(
+        Geometry::<f64>::from_wkb_hex("01zz").err() == Some(WkbError::InvalidHex { offset: 2 })
+    );
+    assert!(Geometry::<f64>::from_wkb_hex("010").err() == Some(WkbError::InvalidHex { offset: 3 }));
+    // POINT EMPTY is written with NaN coordinates
+    let empty = "0101000000000000000000F87F000000000000F87F";
+    assert!(Geometry::<f64>::from_wkb_hex(empty).err() == Some(WkbError::Empty { offset: 5 }));
+}
@@ -1,7 +1,13 @@
-#![feature(float_next_up_down)]
-
-use hprtree::{BBox, HPRTreeWrappingBuilder, Point};
+use hprtree::{
+    flatgeobuf_index_size, BBox, DistanceMetric, Euclidean, FlatGeobufIndex, HPRTreeWrapping,
+    HPRTreeWrappingBuilder, Haversine, ItemId, MappedHPRTree, Point, Polygon,
+};
 use rand::prelude::Distribution;
+use std::ops::ControlFlow;
+
+mod common;
+
+use common::{random_point, random_query_env};
 
 #[test]
 fn random_contain_test() {
@@ -117,9 +123,9 @@ fn random_contain_test() {
 
     for i in query_top
         .into_iter()
-        .chain(query_left.into_iter())
-        .chain(query_bottom.into_iter())
-        .chain(query_right.into_iter())
+        .chain(query_left)
+        .chain(query_bottom)
+        .chain(query_right)
     {
         assert!(!i);
     }
@@ -154,3 +160,697 @@ fn hprtree_end_to_end() {
         assert!(i >= 340);
     }
 }
+
+#[test]
+fn nearest_k_test() {
+    const N: usize = 20_000;
+    const K: usize = 25;
+
+    let mut rng = rand::thread_rng();
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    let distance = |a: &Point, b: &Point| {
+        let dx = a.x as f64 - b.x as f64;
+        let dy = a.y as f64 - b.y as f64;
+        dx * dx + dy * dy
+    };
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+
+        let mut expected: Vec<f64> = points.iter().map(|p| distance(p, &center)).collect();
+        expected.sort_by(f64::total_cmp);
+
+        let result = index.nearest_k(&center, K);
+        assert!(result.len() == K);
+        for (found, expected) in result.iter().zip(expected.iter()) {
+            assert!(distance(&points[*found], &center) == *expected);
+        }
+    }
+
+    let mut small = HPRTreeWrappingBuilder::new(3);
+    small.insert("far", Point { x: 10f32, y: 10f32 });
+    small.insert("near", Point { x: 1f32, y: 1f32 });
+    small.insert("middle", Point { x: 2f32, y: -3f32 });
+    let small = small.build();
+    assert!(small.nearest_k(&Point { x: 0f32, y: 0f32 }, 5) == vec!["near", "middle", "far"]);
+}
+
+#[test]
+fn query_within_distance_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+    let radius_dist = rand::distributions::Uniform::from(0f64..40f64);
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    let within = |a: &Point, b: &Point, radius: f64| {
+        let dx = a.x as f64 - b.x as f64;
+        let dy = a.y as f64 - b.y as f64;
+        dx * dx + dy * dy <= radius * radius
+    };
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+        let radius = radius_dist.sample(&mut rng);
+
+        let expected = points.iter().filter(|p| within(p, &center, radius)).count();
+        let result = index.query_within_distance(&center, radius);
+        assert!(result.len() == expected);
+        for i in result {
+            assert!(within(&points[i], &center, radius));
+        }
+    }
+}
+
+#[test]
+fn haversine_test() {
+    const N: usize = 50_000;
+
+    let metric = Haversine::default();
+
+    let mut rng = rand::thread_rng();
+    let radius_dist = rand::distributions::Uniform::from(0f64..2_000_000f64);
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point: Point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    for _ in 0..50 {
+        let center = random_point(&mut rng);
+        let radius = radius_dist.sample(&mut rng);
+
+        let expected = points
+            .iter()
+            .filter(|p| metric.distance(p, &center) <= radius)
+            .count();
+        let result = index.query_within_distance_with_metric(&center, radius, &metric);
+        assert!(result.len() == expected);
+
+        let nearest = index.nearest_k_with_metric(&center, 1, &metric);
+        let closest = points
+            .iter()
+            .map(|p| metric.distance(p, &center))
+            .min_by(f64::total_cmp)
+            .unwrap();
+        assert!(metric.distance(&points[nearest[0]], &center) == closest);
+    }
+}
+
+#[test]
+fn query_iter_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point: Point = random_point(&mut rng);
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    for _ in 0..50 {
+        let query_env = random_query_env();
+
+        let expected = index.query(&query_env);
+        let result: Vec<usize> = index.query_iter(&query_env).copied().collect();
+        assert!(result == expected);
+    }
+
+    let mut small = HPRTreeWrappingBuilder::new(3);
+    small.insert("Bob", Point { x: 0f32, y: 0f32 });
+    small.insert("Alice", Point { x: 1f32, y: 1f32 });
+    small.insert("Annie", Point { x: 20f32, y: 1f32 });
+    let small = small.build();
+    let query_env = BBox {
+        minx: -5f32,
+        miny: -5f32,
+        maxx: 5f32,
+        maxy: 5f32,
+    };
+    assert!(small.query_iter(&query_env).count() == 2);
+    assert!(small.query_iter(&query_env).any(|i| *i == "Alice"));
+}
+
+#[test]
+fn non_clone_test() {
+    trait Named {
+        fn name(&self) -> String;
+    }
+
+    struct Numbered(usize);
+
+    impl Named for Numbered {
+        fn name(&self) -> String {
+            format!("#{}", self.0)
+        }
+    }
+
+    let mut index: HPRTreeWrappingBuilder<Box<dyn Named>> = HPRTreeWrappingBuilder::new(1000);
+    for i in 0..1000 {
+        index.insert(
+            Box::new(Numbered(i)),
+            Point {
+                x: (i % 100) as f32,
+                y: (i / 100) as f32,
+            },
+        );
+    }
+    let index = index.build();
+    assert!(index.len() == 1000);
+
+    let query_env = BBox {
+        minx: 42f32,
+        miny: 5f32,
+        maxx: 42f32,
+        maxy: 5f32,
+    };
+    let found: Vec<String> = index.query_iter(&query_env).map(|i| i.name()).collect();
+    assert!(found == vec!["#542".to_string()]);
+}
+
+#[test]
+fn query_visit_test() {
+    let mut index = HPRTreeWrappingBuilder::new(10_000);
+    for i in 0..10_000 {
+        index.insert(
+            i,
+            Point {
+                x: (i % 100) as f32,
+                y: (i / 100) as f32,
+            },
+        );
+    }
+    let index = index.build();
+
+    let query_env = BBox {
+        minx: 10f32,
+        miny: 10f32,
+        maxx: 29f32,
+        maxy: 29f32,
+    };
+
+    let mut sum = 0;
+    let mut count = 0;
+    let flow = index.query_visit(&query_env, |i| {
+        sum += i;
+        count += 1;
+        ControlFlow::Continue(())
+    });
+    assert!(flow.is_continue());
+    assert!(count == 400);
+    assert!(sum == index.query(&query_env).iter().sum::<usize>());
+
+    let mut first_even_row = None;
+    let flow = index.query_visit(&query_env, |i| {
+        if (i / 100) % 2 == 0 {
+            first_even_row = Some(*i);
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    });
+    assert!(flow.is_break());
+    assert!(first_even_row.is_some_and(|i| (i / 100) % 2 == 0));
+}
+
+#[test]
+fn count_and_any_test() {
+    const N: usize = 50_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point: Point = random_point(&mut rng);
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    for _ in 0..100 {
+        let query_env = random_query_env();
+
+        let expected = index.query(&query_env).len();
+        assert!(index.count(&query_env) == expected);
+        assert!(index.any(&query_env) == (expected != 0));
+    }
+
+    assert!(index.count(&index.extent()) == N);
+
+    let mut small = HPRTreeWrappingBuilder::new(3);
+    small.insert("Bob", Point { x: 0f32, y: 0f32 });
+    small.insert("Alice", Point { x: 1f32, y: 1f32 });
+    small.insert("Annie", Point { x: 20f32, y: 1f32 });
+    let small = small.build();
+    let query_env = BBox {
+        minx: -5f32,
+        miny: -5f32,
+        maxx: 5f32,
+        maxy: 5f32,
+    };
+    assert!(small.count(&query_env) == 2);
+    assert!(small.any(&query_env));
+}
+
+#[test]
+fn query_polygon_test() {
+    const N: usize = 100_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut points = Vec::with_capacity(N);
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for i in 0..N {
+        let point = random_point(&mut rng);
+        points.push(point.clone());
+        index.insert(i, point);
+    }
+    let index = index.build();
+
+    // a concave star with a square hole in the middle
+    let star: Vec<Point> = (0..10)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::PI / 5f32;
+            let radius = if i % 2 == 0 { 80f32 } else { 30f32 };
+            Point {
+                x: 10f32 + radius * angle.cos(),
+                y: 5f32 + radius * angle.sin(),
+            }
+        })
+        .collect();
+    let hole = vec![
+        Point { x: 0f32, y: -5f32 },
+        Point { x: 20f32, y: -5f32 },
+        Point { x: 20f32, y: 15f32 },
+        Point { x: 0f32, y: 15f32 },
+    ];
+    let polygon = Polygon::with_holes(star.clone(), vec![hole]);
+
+    let expected: Vec<usize> = (0..N).filter(|i| polygon.contains(&points[*i])).collect();
+    let mut result = index.query_polygon_with_holes(&polygon);
+    result.sort();
+    assert!(!expected.is_empty());
+    assert!(result == expected);
+
+    let triangle = [
+        Point { x: 0f32, y: 0f32 },
+        Point { x: 50f32, y: 0f32 },
+        Point { x: 0f32, y: 50f32 },
+        Point { x: 0f32, y: 0f32 },
+    ];
+    let mut result = index.query_polygon(&triangle);
+    result.sort();
+    let expected: Vec<usize> = (0..N)
+        .filter(|i| {
+            points[*i].x > 0f32 && points[*i].y > 0f32 && points[*i].x + points[*i].y < 50f32
+        })
+        .collect();
+    assert!(result == expected);
+
+    // the star and the triangle overlap, the elements within both are found once
+    let polygons = [polygon.clone(), Polygon::new(triangle.to_vec())];
+    let mut result = index.query_multi_polygon(&polygons);
+    result.sort();
+    let expected: Vec<usize> = (0..N)
+        .filter(|i| polygons.iter().any(|polygon| polygon.contains(&points[*i])))
+        .collect();
+    assert!(result == expected);
+    let mut visited = 0;
+    let _ = index.query_visit_multi_polygon(&polygons, |_| {
+        visited += 1;
+        ControlFlow::Continue(())
+    });
+    assert!(visited == expected.len());
+    assert!(index.query_multi_polygon(&[]).is_empty());
+}
+
+#[test]
+fn coordinate_type_test() {
+    // offsets this small are lost when the coordinates are stored as f32
+    const ORIGIN: f64 = 1_000_000_000f64;
+    let mut index = HPRTreeWrappingBuilder::new(1000);
+    for val in 0..1000 {
+        let point = Point {
+            x: ORIGIN + (val % 40) as f64 * 0.01,
+            y: ORIGIN + (val / 40) as f64 * 0.01,
+        };
+        index.insert(val, point);
+    }
+    let index = index.build();
+
+    let result = index.query(&BBox::new(ORIGIN, ORIGIN, ORIGIN + 0.095, ORIGIN + 0.045));
+    assert!(result.len() == 50);
+    for val in result {
+        assert!(val % 40 < 10 && val / 40 < 5);
+    }
+    let nearest = index.nearest_k(
+        &Point {
+            x: ORIGIN,
+            y: ORIGIN,
+        },
+        1,
+    );
+    assert!(nearest.len() == 1 && nearest[0] == 0);
+
+    let mut index = HPRTreeWrappingBuilder::new(10_000);
+    for val in 0..10_000i32 {
+        let point = Point {
+            x: (val % 100 - 50) * 1_000,
+            y: (val / 100 - 50) * 1_000,
+        };
+        index.insert(val, point);
+    }
+    let index = index.build();
+
+    let query_env = BBox::new(-10_000, 0, 9_999, 4_000);
+    let result = index.query(&query_env);
+    assert!(result.len() == 20 * 5);
+    assert!(index.count(&query_env) == result.len());
+}
+
+#[test]
+fn node_capacity_and_hilbert_level_test() {
+    const N: usize = 5_000;
+
+    let mut rng = rand::thread_rng();
+    let points: Vec<Point> = (0..N).map(|_| random_point(&mut rng)).collect();
+
+    for (node_capacity, hilbert_level) in [(2, 1), (3, 8), (16, 12), (64, 16), (N, 16), (N * 2, 16)]
+    {
+        let mut index = HPRTreeWrappingBuilder::new(N)
+            .with_node_capacity(node_capacity)
+            .with_hilbert_level(hilbert_level);
+        for (val, point) in points.iter().enumerate() {
+            index.insert(val, point.clone());
+        }
+        let index = index.build();
+        assert!(index.node_capacity() == node_capacity);
+
+        for _ in 0..20 {
+            let query_env = random_query_env();
+            let expected = points.iter().filter(|p| query_env.contains(p)).count();
+            let result = index.query(&query_env);
+            assert!(result.len() == expected);
+            assert!(index.count(&query_env) == expected);
+            assert!(index.query_iter(&query_env).count() == expected);
+            for val in result {
+                assert!(query_env.contains(&points[val]));
+            }
+        }
+
+        let point = Point { x: 0f32, y: 0f32 };
+        let mut distances: Vec<f64> = points
+            .iter()
+            .map(|p| Euclidean.distance(&point, p))
+            .collect();
+        distances.sort_by(f64::total_cmp);
+        let nearest = index.nearest_k(&point, 10);
+        for (i, val) in nearest.iter().enumerate() {
+            assert!(Euclidean.distance(&point, &points[*val]) == distances[i]);
+        }
+    }
+}
+
+#[test]
+fn node_overlap_test() {
+    fn overlap_area(a: &BBox, b: &BBox) -> f64 {
+        let width = (a.maxx.min(b.maxx) - a.minx.max(b.minx)).max(0f32) as f64;
+        let height = (a.maxy.min(b.maxy) - a.miny.max(b.miny)).max(0f32) as f64;
+        width * height
+    }
+
+    const N: usize = 20_000;
+
+    let mut rng = rand::thread_rng();
+    // lat/lon data, the axes span different ranges
+
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for val in 0..N {
+        let point = random_point(&mut rng);
+        index.insert(val, point);
+    }
+    let index = index.build();
+    let extent_area = index.extent().area();
+
+    for layer in 0..index.depth() {
+        let bounds = index.layer_bounds(layer);
+
+        // the nodes of well packed uniform data cover the extent about once and barely overlap, a broken hilbert order covers it many times over
+        let coverage = bounds.iter().map(|b| b.area()).sum::<f64>() / extent_area;
+        assert!(
+            coverage < 2.0,
+            "layer {layer} covers the extent {coverage} times"
+        );
+
+        let mut overlap = 0f64;
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                overlap += overlap_area(a, b);
+            }
+        }
+        let overlap = overlap / extent_area;
+        assert!(overlap < 1.0, "layer {layer} has an overlap of {overlap}");
+    }
+}
+
+#[test]
+fn mapped_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+
+    let mut index = HPRTreeWrappingBuilder::new(N).with_node_capacity(10);
+    for val in 0..N as u32 {
+        let point = random_point(&mut rng);
+        index.insert(val, point);
+    }
+    let index = index.build();
+
+    let mut bytes = Vec::new();
+    index.write_mapped(&mut bytes).unwrap();
+
+    let mapped = MappedHPRTree::<u32, f64>::from_bytes(&bytes).unwrap();
+    assert!(mapped.len() == N);
+    assert!(mapped.node_capacity() == 10);
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let result: Vec<u32> = mapped
+            .query(&query_env)
+            .iter()
+            .map(|item| item.get())
+            .collect();
+        assert!(result == index.query(&query_env));
+    }
+
+    let mut corrupted = bytes.clone();
+    corrupted[bytes.len() / 2] ^= 1;
+    assert!(mapped.verify_checksum().is_ok());
+    assert!(MappedHPRTree::<u32, f64>::from_bytes(&corrupted)
+        .unwrap()
+        .verify_checksum()
+        .is_err());
+}
+
+#[test]
+fn flatgeobuf_layout_test() {
+    fn node(bytes: &[u8], index: usize) -> ([f64; 4], u64) {
+        let node = &bytes[index * 40..(index + 1) * 40];
+        let mut bbox = [0f64; 4];
+        for (i, value) in bbox.iter_mut().enumerate() {
+            *value = f64::from_le_bytes(node[i * 8..(i + 1) * 8].try_into().unwrap());
+        }
+        (bbox, u64::from_le_bytes(node[32..40].try_into().unwrap()))
+    }
+
+    // 5 features with a node size of 2 make up the levels 5, 3, 2, 1 which are stored root first
+    let mut index = HPRTreeWrappingBuilder::new(5).with_node_capacity(2);
+    for i in 0..5 {
+        index.insert(i, Point { x: i, y: 0 });
+    }
+    let index = index.build();
+    let mut bytes = Vec::new();
+    index
+        .write_flatgeobuf_index(&mut bytes, |i| *i as u64 * 10)
+        .unwrap();
+    assert!(bytes.len() == 11 * 40);
+
+    assert!(node(&bytes, 0) == ([0f64, 0f64, 4f64, 0f64], 1));
+    assert!(node(&bytes, 1).1 == 3);
+    assert!(node(&bytes, 2).1 == 5);
+    assert!(node(&bytes, 3).1 == 6);
+    assert!(node(&bytes, 4).1 == 8);
+    assert!(node(&bytes, 5).1 == 10);
+    for i in 0..5 {
+        let (bbox, offset) = node(&bytes, 6 + i);
+        assert!(bbox[0] == bbox[2] && offset == bbox[0] as u64 * 10);
+    }
+
+    let fgb = FlatGeobufIndex::new(&bytes, 5, 2).unwrap();
+    let result = fgb.search(&BBox::new(0.5f64, -1f64, 3f64, 1f64));
+    assert!(result.iter().map(|r| r.offset).collect::<Vec<u64>>() == [10, 20, 30]);
+
+    // fewer features than the node size still get a root
+    let mut index = HPRTreeWrappingBuilder::new(3);
+    for i in 0..3 {
+        index.insert(i, Point { x: i, y: i });
+    }
+    let mut bytes = Vec::new();
+    index
+        .build()
+        .write_flatgeobuf_index(&mut bytes, |i| *i as u64)
+        .unwrap();
+    assert!(Some(bytes.len()) == flatgeobuf_index_size(3, 16));
+    assert!(node(&bytes, 0) == ([0f64, 0f64, 2f64, 2f64], 1));
+}
+
+#[test]
+fn remove_and_update_position_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let mut random_point = || random_point(&mut rng);
+
+    let mut model: Vec<Option<Point>> = Vec::with_capacity(N);
+    let mut index = HPRTreeWrappingBuilder::new(N).with_ids();
+    for val in 0..N {
+        let point = random_point();
+        model.push(Some(point.clone()));
+        assert!(index.insert(val, point) == ItemId(val));
+    }
+    let mut index = index.build();
+    let query_envs: Vec<BBox> = (0..20)
+        .map(|_| {
+            let mut query_env = BBox::default();
+            query_env.expand_to_include_point(&random_point());
+            query_env.expand_to_include_point(&random_point());
+            query_env
+        })
+        .collect();
+
+    let check = |index: &HPRTreeWrapping<usize>, model: &[Option<Point>]| {
+        assert!(index.len() == model.iter().flatten().count());
+        for (id, point) in model.iter().enumerate() {
+            assert!(index.get(ItemId(id)) == point.as_ref().map(|_| &id));
+        }
+        for query_env in &query_envs {
+            let expected: Vec<usize> = model
+                .iter()
+                .enumerate()
+                .filter(|(_, point)| point.as_ref().is_some_and(|p| query_env.contains(p)))
+                .map(|(id, _)| id)
+                .collect();
+            let mut result = index.query(query_env);
+            result.sort();
+            let mut iter_result: Vec<usize> = index.query_iter(query_env).copied().collect();
+            iter_result.sort();
+            assert!(result == expected);
+            assert!(iter_result == expected);
+            assert!(index.count(query_env) == expected.len());
+        }
+        let center = Point { x: 0f32, y: 0f32 };
+        for val in index.nearest_k(&center, 50) {
+            assert!(model[val].is_some());
+        }
+    };
+
+    for id in (0..N).step_by(5) {
+        assert!(index.remove(ItemId(id)));
+        model[id] = None;
+    }
+    assert!(!index.remove(ItemId(0)));
+    assert!(!index.needs_compaction());
+    check(&index, &model);
+    assert!(index.write_mapped(&mut Vec::new()).is_err());
+
+    for id in (1..N).step_by(50) {
+        let point = random_point();
+        assert!(index.update_position(ItemId(id), point.clone()));
+        model[id] = Some(point);
+    }
+    assert!(!index.update_position(ItemId(0), random_point()));
+    // moving an element outside of the extent grows the extent
+    assert!(index.update_position(ItemId(1), Point { x: 500f32, y: 0f32 }));
+    model[1] = Some(Point { x: 500f32, y: 0f32 });
+    assert!(index.extent().maxx == 500f32);
+    assert!(index.query(&BBox::new(499f32, -1f32, 501f32, 1f32)) == [1]);
+    check(&index, &model);
+
+    for id in (2..N).step_by(5) {
+        index.remove(ItemId(id));
+        model[id] = None;
+    }
+    assert!(index.needs_compaction());
+    check(&index, &model);
+
+    index.compact();
+    assert!(!index.needs_compaction());
+    check(&index, &model);
+    assert!(index.write_mapped(&mut Vec::new()).is_ok());
+}
+
+#[test]
+fn from_iter_test() {
+    const N: usize = 10_000;
+
+    let mut rng = rand::thread_rng();
+    let items: Vec<(usize, Point)> = (0..N).map(|val| (val, random_point(&mut rng))).collect();
+
+    let mut inserted = HPRTreeWrappingBuilder::new(N);
+    for (val, point) in items.iter().cloned() {
+        inserted.insert(val, point);
+    }
+    let inserted = inserted.build();
+
+    let mut extended = HPRTreeWrappingBuilder::new(0).with_ids();
+    extended.extend(items.iter().take(N / 2).cloned());
+    extended.extend(items.iter().skip(N / 2).cloned());
+    let trees = [
+        items
+            .iter()
+            .cloned()
+            .collect::<HPRTreeWrappingBuilder<usize>>()
+            .with_ids()
+            .build(),
+        extended.build(),
+        items.iter().cloned().collect(),
+    ];
+
+    for tree in &trees {
+        assert!(tree.len() == N);
+        assert!(tree.extent().minx == inserted.extent().minx);
+        assert!(tree.extent().maxy == inserted.extent().maxy);
+    }
+    // a tree collected directly keeps no ids
+    for tree in &trees[..2] {
+        assert!(tree.get(ItemId(N - 1)) == Some(&(N - 1)));
+    }
+    assert!(trees[2].get(ItemId(N - 1)).is_none());
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let expected = inserted.query(&query_env);
+        for tree in &trees {
+            assert!(tree.query(&query_env) == expected);
+        }
+    }
+}
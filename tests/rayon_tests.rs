@@ -0,0 +1,71 @@
+#![cfg(feature = "rayon")]
+
+use hprtree::{BBox, HPRTreeBuilder, HPRTreeWrappingBuilder, Point};
+
+mod common;
+
+use common::{random_points, IndexableUsize};
+
+/// Returns random points on a coarse grid, so plenty of elements share a hilbert cell and the order depends on ties being broken by position
+fn random_grid_points(n: usize) -> Vec<Point> {
+    random_points(n)
+        .into_iter()
+        .map(|point: Point| Point {
+            x: point.x.round(),
+            y: point.y.round(),
+        })
+        .collect()
+}
+
+#[test]
+fn par_build_test() {
+    for (n, node_capacity) in [(0, 16), (15, 16), (16, 16), (1_000, 2), (100_000, 16)] {
+        let mut index = HPRTreeBuilder::new(n).with_node_capacity(node_capacity);
+        for (val, point) in random_grid_points(n).into_iter().enumerate() {
+            index.insert(IndexableUsize { point, val });
+        }
+        let sequential = index.clone().build();
+        let parallel = index.par_build();
+
+        assert!(parallel.len() == sequential.len());
+        assert!(parallel.depth() == sequential.depth());
+        // the FlatGeobuf index contains the bounds of every node and the order of the elements
+        let mut expected = Vec::new();
+        sequential
+            .write_flatgeobuf_index(&mut expected, |item| item.val as u64)
+            .unwrap();
+        let mut result = Vec::new();
+        parallel
+            .write_flatgeobuf_index(&mut result, |item| item.val as u64)
+            .unwrap();
+        assert!(result == expected);
+    }
+}
+
+#[test]
+fn par_build_wrapping_test() {
+    for (n, node_capacity) in [(0, 16), (15, 16), (16, 16), (1_000, 2), (100_000, 16)] {
+        let mut index = HPRTreeWrappingBuilder::new(n).with_node_capacity(node_capacity);
+        for (val, point) in random_grid_points(n).into_iter().enumerate() {
+            index.insert(val, point);
+        }
+        let mut sorted = index.clone();
+        sorted.sort_items();
+        let mut par_sorted = index.clone();
+        par_sorted.par_sort_items();
+
+        for (sequential, parallel) in [
+            (index.clone().build(), index.par_build()),
+            (sorted.build_sorted(), par_sorted.par_build_sorted()),
+        ] {
+            let mut expected = Vec::new();
+            sequential.write_mapped(&mut expected).unwrap();
+            let mut result = Vec::new();
+            parallel.write_mapped(&mut result).unwrap();
+            assert!(result == expected);
+
+            let query_env = BBox::new(-10f32, -10f32, 10f32, 10f32);
+            assert!(parallel.query(&query_env) == sequential.query(&query_env));
+        }
+    }
+}
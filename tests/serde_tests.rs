@@ -0,0 +1,179 @@
+#![cfg(feature = "serde")]
+
+use hprtree::{
+    BBox, HPRTree, HPRTreeBuilder, HPRTreeWrapping, HPRTreeWrappingBuilder, ItemId, Point,
+};
+
+mod common;
+
+use common::{random_points, random_query_env, IndexableUsize};
+
+#[test]
+fn roundtrip_test() {
+    const N: usize = 10_000;
+
+    let points = random_points(N);
+    let mut index = HPRTreeBuilder::new(N);
+    for (val, point) in points.iter().enumerate() {
+        index.insert(IndexableUsize {
+            point: point.clone(),
+            val,
+        });
+    }
+    let index = index.build();
+
+    let json = serde_json::to_string(&index).unwrap();
+    let restored: HPRTree<IndexableUsize> = serde_json::from_str(&json).unwrap();
+    assert!(restored.len() == index.len());
+    assert!(restored.depth() == index.depth());
+    assert!(serde_json::to_string(&restored).unwrap() == json);
+
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        let expected: Vec<usize> = index.query(&query_env).iter().map(|i| i.val).collect();
+        let result: Vec<usize> = restored.query(&query_env).iter().map(|i| i.val).collect();
+        assert!(result == expected);
+    }
+}
+
+#[test]
+fn roundtrip_wrapping_test() {
+    const N: usize = 10_000;
+
+    let points = random_points(N);
+    let mut index = HPRTreeWrappingBuilder::new(N).with_node_capacity(8);
+    for (val, point) in points.into_iter().enumerate() {
+        index.insert(val, point);
+    }
+    let index = index.build();
+
+    let json = serde_json::to_string(&index).unwrap();
+    let restored: HPRTreeWrapping<usize> = serde_json::from_str(&json).unwrap();
+    assert!(restored.node_capacity() == 8);
+    assert!(serde_json::to_string(&restored).unwrap() == json);
+
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        assert!(restored.query(&query_env) == index.query(&query_env));
+    }
+}
+
+#[test]
+fn invalid_structure_test() {
+    const N: usize = 1_000;
+
+    let mut index = HPRTreeWrappingBuilder::new(N).with_ids();
+    for (val, point) in random_points::<f32>(N).into_iter().enumerate() {
+        index.insert(val, point);
+    }
+    let value = serde_json::to_value(index.build()).unwrap();
+    assert!(serde_json::from_value::<HPRTreeWrapping<usize>>(value.clone()).is_ok());
+
+    let tampered: [fn(&mut serde_json::Value); 9] = [
+        |v| v["node_capacity"] = 1.into(),
+        |v| v["node_capacity"] = 32.into(),
+        |v| v["layer_start_index"][1] = 3.into(),
+        |v| {
+            v["node_bounds"].as_array_mut().unwrap().pop();
+        },
+        |v| v["node_bounds"][0]["maxx"] = (-500f32).into(),
+        |v| v["extent"]["minx"] = 0f32.into(),
+        |v| v["ids"][0] = v["ids"][1].clone(),
+        |v| v["hilbert_level"] = 0.into(),
+        |v| v["tombstones"] = vec![u64::MAX; 16].into(),
+    ];
+    for tamper in tampered {
+        let mut value = value.clone();
+        tamper(&mut value);
+        assert!(serde_json::from_value::<HPRTreeWrapping<usize>>(value).is_err());
+    }
+}
+
+#[test]
+fn removed_roundtrip_test() {
+    const N: usize = 1_000;
+
+    let mut index = HPRTreeWrappingBuilder::new(N).with_ids();
+    for (val, point) in random_points(N).into_iter().enumerate() {
+        index.insert(val, point);
+    }
+    let mut index = index.build();
+    for id in (0..N).step_by(10) {
+        index.remove(ItemId(id));
+    }
+    index.update_position(ItemId(1), Point { x: 500f32, y: 0f32 });
+
+    let json = serde_json::to_string(&index).unwrap();
+    let mut restored: HPRTreeWrapping<usize> = serde_json::from_str(&json).unwrap();
+    assert!(restored.len() == N - N / 10);
+    assert!(restored.get(ItemId(0)).is_none());
+    assert!(restored.get(ItemId(1)) == Some(&1));
+    assert!(restored.query(&BBox::new(499f32, -1f32, 501f32, 1f32)) == [1]);
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        assert!(restored.query(&query_env) == index.query(&query_env));
+    }
+
+    restored.compact();
+    assert!(restored.len() == N - N / 10);
+    assert!(restored.get(ItemId(0)).is_none());
+    assert!(restored.get(ItemId(N - 1)) == Some(&(N - 1)));
+}
+
+#[test]
+fn without_ids_test() {
+    const N: usize = 1_000;
+
+    let mut index = HPRTreeWrappingBuilder::new(N);
+    for (val, point) in random_points(N).into_iter().enumerate() {
+        index.insert(val, point);
+    }
+    let index = index.build();
+
+    // trees serialised before elements had ids are missing these, they are restored as trees built without ids
+    let mut value = serde_json::to_value(&index).unwrap();
+    for key in ["ids", "tombstones", "stale", "hilbert_level"] {
+        value.as_object_mut().unwrap().remove(key);
+    }
+    let restored: HPRTreeWrapping<usize> = serde_json::from_value(value).unwrap();
+    assert!(restored.len() == N);
+    assert!(restored.get(ItemId(0)).is_none());
+    for _ in 0..20 {
+        let query_env = random_query_env();
+        assert!(restored.query(&query_env) == index.query(&query_env));
+    }
+}
+
+#[test]
+fn sparse_ids_test() {
+    const N: usize = 100;
+
+    let mut index = HPRTreeWrappingBuilder::new(N).with_ids();
+    for (val, point) in random_points::<f32>(N).into_iter().enumerate() {
+        index.insert(val, point);
+    }
+    let value = serde_json::to_value(index.build()).unwrap();
+    let position = value["ids"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .position(|id| id == 1)
+        .unwrap();
+
+    // nothing is allocated by the value of the ids, so huge ones are just as fine as small ones
+    let mut sparse = value.clone();
+    sparse["ids"][position] = (u64::MAX - 1).into();
+    let restored: HPRTreeWrapping<usize> = serde_json::from_value(sparse).unwrap();
+    assert!(restored.get(ItemId(1)).is_none());
+    assert!(restored.get(ItemId(u64::MAX as usize - 1)) == Some(&1));
+    assert!(restored.get(ItemId(0)) == Some(&0));
+
+    // the number of handed out ids would not fit
+    let mut overflowing = value.clone();
+    overflowing["ids"][position] = u64::MAX.into();
+    assert!(serde_json::from_value::<HPRTreeWrapping<usize>>(overflowing).is_err());
+
+    let mut missing = value;
+    missing["ids"].as_array_mut().unwrap().pop();
+    assert!(serde_json::from_value::<HPRTreeWrapping<usize>>(missing).is_err());
+}